@@ -0,0 +1,94 @@
+// Benchmark of the LF-mapping inverse BWT against the previous radix-sort implementation
+// Run with: cargo bench --bench bwt_decode
+
+// Explicit return statements are the house style of this crate
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{measure, sample_text};
+use midterm_problem::bwt;
+
+// Previous inverse BWT: radix-sorts (symbol, index) tuples, cloning a fresh buffer on every pass
+// (the per-character dbg! call is left out so that only the algorithm is measured)
+
+fn radix_sort(t: &mut Vec<[i32; 2]>) {
+    let n = t.len();
+    let maxi = n.max(300);
+    let mut c: Vec<i32> = vec![0; maxi];
+
+    for k in (0..2).rev() {
+        c.fill(0);
+
+        for item in t.iter() {
+            c[item[k] as usize] += 1;
+        }
+
+        for i in 1..maxi {
+            c[i] += c[i - 1];
+        }
+
+        let mut temp_t: Vec<[i32; 2]> = vec![[0, 0]; n];
+        for i in (0..n).rev() {
+            let val = t[i][k] as usize;
+            temp_t[(c[val] - 1) as usize] = t[i];
+            c[val] -= 1;
+        }
+        *t = temp_t.clone();
+    }
+}
+
+fn legacy_decode(data: &[u8], index: usize) -> Vec<u8> {
+    let mut m: Vec<u8> = Vec::new();
+
+    let mut v: Vec<[i32; 2]> = Vec::new();
+    for (i, &b) in data.iter().enumerate() {
+        if i == index {
+            v.push([0, v.len() as i32]);
+        }
+        v.push([b as i32 + 1, v.len() as i32]);
+    }
+    if index == data.len() {
+        v.push([0, data.len() as i32]);
+    }
+
+    radix_sort(&mut v);
+
+    let mut curr_index = index;
+    for _ in 0..v.len() {
+        m.push((v[curr_index][0] - 1) as u8);
+        curr_index = v[curr_index][1] as usize;
+    }
+
+    m.pop();
+
+    return m;
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>12} {:>12} {:>8} {:>14} {:>14}",
+        "bytes", "radix-sort", "lf-mapping", "speedup", "radix alloc", "lf alloc"
+    );
+
+    for &len in &[16 * 1024, 256 * 1024, 1024 * 1024, 4 * 1024 * 1024] {
+        let text = sample_text(len);
+        let (encoded, index) = bwt::encode(&text);
+
+        assert_eq!(text, legacy_decode(&encoded, index));
+        assert_eq!(text, bwt::decode(&encoded, index).unwrap());
+
+        let (legacy, legacy_alloc) = measure(5, || legacy_decode(&encoded, index));
+        let (lf, lf_alloc) = measure(5, || bwt::decode(&encoded, index).unwrap());
+
+        println!(
+            "{:>10} {:>12.2?} {:>12.2?} {:>7.1}x {:>12} KB {:>11} KB",
+            len,
+            legacy,
+            lf,
+            legacy.as_secs_f64() / lf.as_secs_f64(),
+            legacy_alloc / 1024,
+            lf_alloc / 1024
+        );
+    }
+}
@@ -0,0 +1,73 @@
+// Helpers shared by the benchmarks: an allocation-counting allocator, a timer and sample inputs
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Allocator wrapper that counts the bytes allocated, to compare the memory churn of implementations
+
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// measure function returns the fastest of several runs of f and the bytes allocated by one run
+
+pub fn measure<T, F: FnMut() -> T>(runs: u32, mut f: F) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut allocated = 0;
+
+    for _ in 0..runs {
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        let start = Instant::now();
+        black_box(f());
+        best = best.min(start.elapsed());
+        allocated = ALLOCATED.load(Ordering::Relaxed) - before;
+    }
+
+    return (best, allocated);
+}
+
+// sample_text function builds a diropql-like text of the given length with a simple xorshift generator
+
+pub fn sample_text(len: usize) -> Vec<u8> {
+    let commands = b"dilopqr";
+    let mut state: u32 = 0x2545_f491;
+    let mut text: Vec<u8> = Vec::with_capacity(len);
+
+    while text.len() < len {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+
+        // Runs of the same command like the output of dpql::write
+        let c = commands[(state % 7) as usize];
+        for _ in 0..(state >> 28) {
+            text.push(c);
+        }
+    }
+
+    text.truncate(len);
+
+    return text;
+}
+
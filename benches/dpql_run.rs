@@ -0,0 +1,108 @@
+// Benchmark of the bytecode diropql interpreter against the previous character interpreter
+// Run with: cargo bench --bench dpql_run
+
+// Explicit return statements are the house style of this crate
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{measure, sample_text};
+use midterm_problem::dpql;
+
+// Previous interpreter: fetches every command with chars().nth and scans all bracket pairs on every jump
+
+fn legacy_read_bytes(prog: &str) -> Vec<u8> {
+    let mut memory_cells: Vec<u8> = vec![0; 10000];
+    let mut mp: usize = 0;
+    let mut ip: usize = 0;
+    let mut oq: Vec<u8> = Vec::new();
+
+    let mut pq_index: Vec<(usize, usize)> = Vec::new();
+    let mut p_indices: Vec<usize> = Vec::new();
+    for (index, c) in prog.chars().enumerate() {
+        if c == 'p' {
+            p_indices.push(index);
+        }
+        else if c == 'q' {
+            if let Some(p_index) = p_indices.pop() {
+                pq_index.push((p_index, index));
+            }
+        }
+    }
+
+    while ip != prog.len() {
+        let c = prog.chars().nth(ip);
+
+        if c == Some('l') {
+            mp = if mp == 0 { 9999 } else { mp - 1 };
+        }
+        else if c == Some('r') {
+            mp = if mp == 9999 { 0 } else { mp + 1 };
+        }
+        else if c == Some('i') {
+            memory_cells[mp] = memory_cells[mp].wrapping_add(1);
+        }
+        else if c == Some('d') {
+            memory_cells[mp] = memory_cells[mp].wrapping_sub(1);
+        }
+        else if c == Some('o') {
+            oq.push(memory_cells[mp]);
+        }
+        else if c == Some('p') && memory_cells[mp] == 0 {
+            for pq in &pq_index {
+                if pq.0 == ip {
+                    ip = pq.1;
+                }
+            }
+        }
+        else if c == Some('q') && memory_cells[mp] != 0 {
+            for pq in &pq_index {
+                if pq.1 == ip {
+                    ip = pq.0;
+                }
+            }
+        }
+
+        ip += 1;
+    }
+
+    return oq;
+}
+
+// loop_program function builds a program of about len commands that spends most of its steps inside loops
+
+fn loop_program(len: usize) -> String {
+    let mut prog = String::new();
+
+    while prog.len() < len {
+        // Move 40 into the next cell, clear it again and print the zero
+        prog.push_str(&"i".repeat(40));
+        prog.push_str("prildqrpdqol");
+    }
+
+    return prog;
+}
+
+fn main() {
+    println!("{:>8} {:>10} {:>12} {:>12} {:>8}", "program", "commands", "legacy", "bytecode", "speedup");
+
+    for &len in &[1024, 4 * 1024, 16 * 1024] {
+        let programs = [("write", dpql::write_bytes(&sample_text(len / 3))), ("loops", loop_program(len))];
+
+        for (name, prog) in &programs {
+            assert_eq!(legacy_read_bytes(prog), dpql::read_bytes(prog).unwrap());
+
+            let (legacy, _) = measure(3, || legacy_read_bytes(prog));
+            let (bytecode, _) = measure(3, || dpql::read_bytes(prog).unwrap());
+
+            println!(
+                "{:>8} {:>10} {:>12.2?} {:>12.2?} {:>7.1}x",
+                name,
+                prog.len(),
+                legacy,
+                bytecode,
+                legacy.as_secs_f64() / bytecode.as_secs_f64()
+            );
+        }
+    }
+}
@@ -0,0 +1,130 @@
+// Benchmark of the table-driven canonical Huffman decoder against the codeword string map decoder of the baseline
+// Run with: cargo bench --bench huffman_decode
+
+// Explicit return statements are the house style of this crate
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{measure, sample_text};
+use midterm_problem::compressor::bitio::{BitReader, BitWriter};
+use midterm_problem::{bwt, huffman, mtf, rle};
+
+use std::collections::HashMap;
+
+// get_bit_str function is the baseline helper that writes the low l bits of n as a string of '0' and '1'
+
+fn get_bit_str(n: i128, l: u8) -> String {
+    let mut v: String = String::new();
+    for i in 0..l {
+        let b = if (n & (1 << i)) != 0 { '1' } else { '0' };
+        v.push(b);
+    }
+
+    v = v.chars().rev().collect();
+
+    return v;
+}
+
+// reconstruct_canon_cb function is the baseline canonical codebook of codeword strings, with u16 symbols
+
+fn reconstruct_canon_cb(canon_freqs: &[u8]) -> HashMap<u16, String> {
+    let mut v: Vec<(u16, u8)> = Vec::new();
+    for (i, &val) in canon_freqs.iter().enumerate() {
+        if val != 0 {
+            v.push((i as u16, val));
+        }
+    }
+
+    v.sort_by(|a, b| if a.1 != b.1 { a.1.cmp(&b.1) } else { a.0.cmp(&b.0) });
+
+    let mut ret: HashMap<u16, String> = HashMap::new();
+    let mut l: u8 = 0;
+    let mut c_canon: i128 = -1;
+    for (c, f) in &v {
+        c_canon += 1;
+        while *f > l {
+            c_canon <<= 1;
+            l += 1;
+        }
+
+        ret.insert(*c, get_bit_str(c_canon, l));
+    }
+
+    return ret;
+}
+
+// baseline_decode function is the decoder of the original code: it takes one byte per bit, pushes every bit to
+// a string and looks the string up in a map of codeword strings
+
+fn baseline_decode(data: &[u8], canon_freqs: &[u8]) -> Vec<u16> {
+    let canon_cb = reconstruct_canon_cb(canon_freqs);
+    let mut rev_canon_cb: HashMap<String, u16> = HashMap::new();
+    for (key, value) in &canon_cb {
+        rev_canon_cb.insert(value.clone(), *key);
+    }
+
+    let mut s = String::new();
+    let mut ret: Vec<u16> = Vec::new();
+    for bit in data {
+        s.push((bit + b'0') as char);
+
+        if rev_canon_cb.contains_key(&s) {
+            let symb = rev_canon_cb.get(&s).copied().unwrap();
+            ret.push(symb);
+            s.clear();
+        }
+    }
+
+    return ret;
+}
+
+// rle_symbols function runs a diropql-like text through the stages that precede Huffman coding
+
+fn rle_symbols(len: usize) -> Vec<u16> {
+    let (bwt_encoded, _) = bwt::encode(&sample_text(len));
+    let bwt_encoded: String = bwt_encoded.iter().map(|&b| b as char).collect();
+    let mtf_encoded = mtf::encode(&bwt_encoded, "dilopqr").unwrap();
+
+    return rle::encode(&mtf_encoded);
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>12} {:>12} {:>8} {:>10} {:>14} {:>14}",
+        "symbols", "baseline", "table", "speedup", "MB/s", "baseline alloc", "table alloc"
+    );
+
+    for &len in &[16 * 1024, 256 * 1024, 1024 * 1024, 4 * 1024 * 1024] {
+        let symbols = rle_symbols(len);
+
+        let mut writer = BitWriter::new();
+        let canon_freqs = huffman::encode(&symbols, &mut writer);
+        let padding = writer.padding();
+        let bytes = writer.into_bytes();
+
+        // The baseline decoder takes the bits unpacked to one byte each, as the baseline encoder produced them
+        let mut reader = BitReader::with_padding(&bytes, padding);
+        let bits: Vec<u8> = std::iter::from_fn(|| reader.read_bit()).map(u8::from).collect();
+
+        let (baseline, baseline_alloc) = measure(3, || baseline_decode(&bits, &canon_freqs));
+        let (table, table_alloc) =
+            measure(3, || huffman::decode(&mut BitReader::with_padding(&bytes, padding), &canon_freqs));
+
+        assert_eq!(
+            baseline_decode(&bits, &canon_freqs),
+            huffman::decode(&mut BitReader::with_padding(&bytes, padding), &canon_freqs).unwrap()
+        );
+
+        println!(
+            "{:>10} {:>12.2?} {:>12.2?} {:>7.1}x {:>10.1} {:>11} KB {:>11} KB",
+            symbols.len(),
+            baseline,
+            table,
+            baseline.as_secs_f64() / table.as_secs_f64(),
+            bytes.len() as f64 / table.as_secs_f64() / 1e6,
+            baseline_alloc / 1024,
+            table_alloc / 1024
+        );
+    }
+}
@@ -0,0 +1,39 @@
+// Benchmark of SA-IS against DC3 suffix array construction
+// Run with: cargo bench --bench suffix_array
+
+// Explicit return statements are the house style of this crate
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{measure, sample_text};
+use midterm_problem::bwt::{SuffixAlgorithm, SuffixArray};
+
+fn main() {
+    println!(
+        "{:>10} {:>12} {:>12} {:>8} {:>14} {:>14}",
+        "bytes", "dc3", "sa-is", "speedup", "dc3 alloc", "sa-is alloc"
+    );
+
+    for &len in &[16 * 1024, 256 * 1024, 1024 * 1024, 4 * 1024 * 1024] {
+        let text: Vec<u32> = sample_text(len).iter().map(|&b| b as u32).collect();
+
+        let (dc3, dc3_alloc) = measure(3, || SuffixAlgorithm::Dc3.suffix_array(&text, 256));
+        let (sais, sais_alloc) = measure(3, || SuffixAlgorithm::SaIs.suffix_array(&text, 256));
+
+        assert_eq!(
+            SuffixAlgorithm::Dc3.suffix_array(&text, 256),
+            SuffixAlgorithm::SaIs.suffix_array(&text, 256)
+        );
+
+        println!(
+            "{:>10} {:>12.2?} {:>12.2?} {:>7.1}x {:>12} KB {:>11} KB",
+            len,
+            dc3,
+            sais,
+            dc3.as_secs_f64() / sais.as_secs_f64(),
+            dc3_alloc / 1024,
+            sais_alloc / 1024
+        );
+    }
+}
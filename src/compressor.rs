@@ -2,4 +2,6 @@
 pub mod bwt;
 pub mod mtf;
 pub mod rle;
-pub mod huffman;
\ No newline at end of file
+pub mod huffman;
+pub mod sais;
+pub mod bitio;
\ No newline at end of file
@@ -0,0 +1,192 @@
+// Bit-level writer and reader over packed bytes; bits are stored most significant bit first
+
+// BitWriter packs bits into a growing vector of bytes
+
+#[derive(Clone, Debug, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    bit_len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        return BitWriter::default();
+    }
+
+    // write_bit function appends a single bit
+
+    pub fn write_bit(&mut self, bit: bool) {
+        if self.bit_len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+
+        if bit {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= 0x80 >> (self.bit_len % 8);
+        }
+
+        self.bit_len += 1;
+    }
+
+    // write_bits function appends the lowest count bits of value, most significant first
+
+    pub fn write_bits(&mut self, value: u64, count: u32) {
+        for shift in (0..count).rev() {
+            self.write_bit((value >> shift) & 1 == 1);
+        }
+    }
+
+    // bit_len function returns the number of bits written so far
+
+    pub fn bit_len(&self) -> usize {
+        return self.bit_len;
+    }
+
+    // padding function returns the number of zero bits that fill up the last byte
+
+    pub fn padding(&self) -> u8 {
+        return ((8 - self.bit_len % 8) % 8) as u8;
+    }
+
+    // into_bytes function returns the packed bytes, with the last byte padded by zero bits
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        return self.bytes;
+    }
+}
+
+// BitReader reads the bits of a slice of packed bytes, ignoring the padding bits at the end
+
+#[derive(Clone, Debug)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    bit_len: usize,
+}
+
+impl<'a> BitReader<'a> {
+    // new function reads every bit of bytes
+
+    pub fn new(bytes: &'a [u8]) -> Self {
+        return BitReader::with_padding(bytes, 0);
+    }
+
+    // with_padding function reads every bit of bytes except the last padding bits
+
+    pub fn with_padding(bytes: &'a [u8], padding: u8) -> Self {
+        let bit_len = (bytes.len() * 8).saturating_sub(padding as usize);
+
+        return BitReader { bytes, pos: 0, bit_len };
+    }
+
+    // read_bit function returns the next bit, or None once every bit has been read
+
+    pub fn read_bit(&mut self) -> Option<bool> {
+        if self.pos >= self.bit_len {
+            return None;
+        }
+
+        let bit = (self.bytes[self.pos / 8] << (self.pos % 8)) & 0x80 != 0;
+        self.pos += 1;
+
+        return Some(bit);
+    }
+
+    // read_bits function returns the next count bits as an integer, or None if fewer bits are left
+
+    pub fn read_bits(&mut self, count: u32) -> Option<u64> {
+        if self.remaining() < count as usize {
+            return None;
+        }
+
+        let mut value: u64 = 0;
+
+        for _ in 0..count {
+            value = (value << 1) | u64::from(self.read_bit()?);
+        }
+
+        return Some(value);
+    }
+
+    // peek_bits function returns the next count bits (at most 57) without consuming them; bits past the end
+    // of the data read as zero
+
+    pub fn peek_bits(&self, count: u32) -> u64 {
+        if count == 0 {
+            return 0;
+        }
+
+        let start = self.pos / 8;
+        let mut window: u64 = 0;
+
+        for i in 0..8 {
+            window = (window << 8) | u64::from(self.bytes.get(start + i).copied().unwrap_or(0));
+        }
+
+        return (window << (self.pos % 8)) >> (64 - count);
+    }
+
+    // skip_bits function consumes count bits, which must not exceed the remaining bits
+
+    pub fn skip_bits(&mut self, count: usize) {
+        assert!(count <= self.remaining(), "cannot skip past the end of the bit stream");
+        self.pos += count;
+    }
+
+    // remaining function returns the number of bits that have not been read yet
+
+    pub fn remaining(&self) -> usize {
+        return self.bit_len - self.pos;
+    }
+}
+
+#[cfg(test)]
+mod bitio_test {
+    use super::*;
+
+    #[test]
+    fn write_read_pt1_bits() {
+        // Bits are packed most significant first and the last byte is padded with zeros
+
+        let mut writer = BitWriter::new();
+        writer.write_bits(0b1011, 4);
+        writer.write_bit(true);
+        writer.write_bits(0x1FF, 9);
+
+        assert_eq!(14, writer.bit_len());
+        assert_eq!(2, writer.padding());
+
+        let bytes = writer.into_bytes();
+        assert_eq!(vec![0b1011_1111, 0b1111_1100], bytes);
+
+        let mut reader = BitReader::with_padding(&bytes, 2);
+        assert_eq!(Some(0b1011), reader.read_bits(4));
+        assert_eq!(Some(true), reader.read_bit());
+        assert_eq!(None, reader.read_bits(10));
+        assert_eq!(Some(0x1FF), reader.read_bits(9));
+        assert_eq!(None, reader.read_bit());
+    }
+
+    #[test]
+    fn peek_pt1_bits() {
+        // Peeking does not consume, works across byte boundaries and reads zeros past the end
+
+        let bytes = [0b1010_1100, 0b0101_0011];
+        let mut reader = BitReader::new(&bytes);
+        reader.skip_bits(6);
+
+        assert_eq!(0b0001_0100, reader.peek_bits(8));
+        assert_eq!(0b00_0101_0011_0000, reader.peek_bits(14));
+        assert_eq!(10, reader.remaining());
+    }
+
+    #[test]
+    fn read_pt1_padding() {
+        // Padding larger than the data leaves nothing to read
+
+        let mut reader = BitReader::with_padding(&[0xFF], 9);
+
+        assert_eq!(0, reader.remaining());
+        assert_eq!(None, reader.read_bit());
+    }
+}
@@ -1,4 +1,6 @@
 #![allow(warnings)]
+use crate::error::{Error, Result};
+
 // lexicographic order for pairs
 fn leq_p(a1: i32, a2: i32, b1: i32, b2: i32) -> bool {
     (a1 < b1) || (a1 == b1) && (a2 <= b2)
@@ -154,113 +156,218 @@ fn construct_suffix_array(T: &Vec<i32>, SA: &mut Vec<i32>, n: i32, K: i32) {
     }
 }
 
-pub fn get_sentinel_idx(text: &String) -> usize {
-    let mut idx: usize = 0;
-    for (i, c) in text.chars().enumerate() {
-        if c as u8 == 0 {
-            idx = i;
-            break;
-        }
-    }
+// Suffix array construction algorithm used by encode_with
 
-    return idx;
+pub trait SuffixArray {
+    // suffix_array function returns the start positions of the sorted suffixes of text, whose symbols are below k
+    fn suffix_array(&self, text: &[u32], k: u32) -> Vec<u32>;
 }
 
-fn radix_sort(t: &mut Vec<[i32; 2]>) { // O(kN)
-    let n = t.len();
-    let maxi = n.max(300);
-    let mut c: Vec<i32> = vec![0; maxi];
+// Suffix array builder using the DC3 (skew) algorithm above
 
-    // Sort from rightmost element to leftmost element
-    for k in (0..2).rev() {
-        // Initialize frequency array
-        c.fill(0);
+pub struct Dc3;
 
-        // Update frequency array
-        for i in 0..n {
-            c[t[i][k] as usize] += 1;
-        }
+impl SuffixArray for Dc3 {
+    fn suffix_array(&self, text: &[u32], k: u32) -> Vec<u32> {
+        let n = text.len();
 
-        // Compute prefix sums
-        for i in 1..maxi {
-            c[i] += c[i - 1];
+        // construct_suffix_array needs n >= 2
+        if n < 2 {
+            return (0..n as u32).collect();
         }
 
-        // sort t in place
-        let mut temp_t: Vec<[i32; 2]> = vec![[0, 0]; n];
-        for i in (0..n).rev() {
-            let val = t[i][k] as usize;
-            temp_t[(c[val] - 1) as usize] = t[i];
-            c[val] -= 1;
+        // Shift symbols to 1..=k and insert 3 zeros at the end
+        let mut t: Vec<i32> = text.iter().map(|&c| c as i32 + 1).collect();
+        t.push(0); t.push(0); t.push(0);
+
+        let mut suffix_array: Vec<i32> = vec![0; n];
+        construct_suffix_array(&t, &mut suffix_array, n as i32, k as i32);
+
+        return suffix_array.iter().map(|&i| i as u32).collect();
+    }
+}
+
+// Suffix array algorithms that can be selected at runtime
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SuffixAlgorithm {
+    #[default]
+    Dc3,
+    SaIs,
+}
+
+impl SuffixArray for SuffixAlgorithm {
+    fn suffix_array(&self, text: &[u32], k: u32) -> Vec<u32> {
+        match self {
+            SuffixAlgorithm::Dc3 => Dc3.suffix_array(text, k),
+            SuffixAlgorithm::SaIs => crate::compressor::sais::SaIs.suffix_array(text, k),
         }
-        *t = temp_t.clone();
     }
 }
 
-pub fn encode(text: &String) -> (String, usize) {
-    // Initialize number array
-    let mut t: Vec<i32> = text.chars().map(|c| c as i32).collect();
-    t.push(0); // Insert sentinel character
+/* encode function computes the BWT of the bytes without a sentinel symbol in the output. Bytes are
+shifted to 1..=256 so that 0 is an out-of-band sentinel during sorting; the sentinel is then dropped from
+the BWT and its position is returned as the primary index, so input bytes of 0 are unambiguous */
 
-    let n = t.len() as i32; // Length of original string + sentinel
-    let K: i32 = 256; // ASCII chars as keys
+pub fn encode(text: &[u8]) -> (Vec<u8>, usize) {
+    return encode_with(text, &SuffixAlgorithm::default());
+}
 
-    // Insert 3 zeros at the end
-    t.push(0); t.push(0); t.push(0);
+// encode_with function is encode using the given suffix array algorithm
 
-    // construct suffix array using DC3
-    let mut suffix_array: Vec<i32> = vec![0; n as usize];
-    construct_suffix_array(&t, &mut suffix_array, n, K);
+pub fn encode_with(text: &[u8], algorithm: &dyn SuffixArray) -> (Vec<u8>, usize) {
+    // Initialize number array
+    let mut t: Vec<u32> = text.iter().map(|&b| b as u32 + 1).collect();
+    t.push(0); // Insert out-of-band sentinel
 
-    // Get BWT index, i.e., the index of 0 in the suffix array
+    let suffix_array = algorithm.suffix_array(&t, 257);
+
+    // Generate BWT bytes from the symbol preceding every suffix; the suffix starting at 0 is
+    // preceded by the sentinel, whose row is the primary index
     let mut idx: usize = 0;
-    let n = suffix_array.len() as i32;
-    for i in 0..suffix_array.len() {
-        if suffix_array[i] == 0 {
+    let mut v: Vec<u8> = Vec::with_capacity(text.len());
+    for (i, &start) in suffix_array.iter().enumerate() {
+        if start == 0 {
             idx = i;
+        } else {
+            v.push((t[start as usize - 1] - 1) as u8);
         }
+    }
 
-        // decrement by -1 but maintain mod n
-        suffix_array[i] = (((suffix_array[i] - 1) % n) + n) % n;
+    return (v, idx);
+}
+
+/* decode function inverts encode in O(n) using LF-mapping. Row 0 of the sorted rotations starts with the
+sentinel, so its last column holds the final byte of the text; following LF(i) = C[L[i]] + rank(L[i], i)
+from there yields the text back to front. The sentinel sits in the last column at the primary index */
+
+pub fn decode(data: &[u8], index: usize) -> Result<Vec<u8>> {
+    let n = data.len();
+
+    // The primary index must point to one of the n + 1 rotations other than row 0, which ends
+    // with the last byte of a non-empty text
+    if index > n || (index == 0 && n > 0) {
+        return Err(Error::BwtIndexOutOfRange { index: index as u64, len: n });
     }
 
-    // Generate BWT string
-    let mut v = String::new();
-    for i in 0..(n as usize) {
-        v.push(t[suffix_array[i] as usize] as u8 as char);
+    // Count occurrences of every byte
+    let mut counts: [usize; 256] = [0; 256];
+    for &b in data {
+        counts[b as usize] += 1;
     }
 
-    return (v, idx);
+    // C[c] = number of symbols smaller than c, counting the sentinel as the smallest
+    let mut first: [usize; 256] = [0; 256];
+    let mut sum: usize = 1;
+    for c in 0..256 {
+        first[c] = sum;
+        sum += counts[c];
+    }
+
+    // LF-mapping of every row of the last column, packed with the byte in that row so that
+    // each step of the walk touches a single entry
+    let mut lf: Vec<u64> = vec![0; n + 1];
+    let mut seen: [usize; 256] = [0; 256];
+    for (i, &b) in data.iter().enumerate() {
+        let row = if i < index { i } else { i + 1 };
+        lf[row] = (((first[b as usize] + seen[b as usize]) as u64) << 8) | b as u64;
+        seen[b as usize] += 1;
+    }
+
+    // Walk backwards from the row that starts with the sentinel
+    let mut m: Vec<u8> = vec![0; n];
+    let mut row: usize = 0;
+    for k in (0..n).rev() {
+        let entry = lf[row];
+        m[k] = entry as u8;
+        row = (entry >> 8) as usize;
+    }
+
+    return Ok(m);
 }
 
-pub fn decode(text: &String, index: usize) -> String {
-    let mut m = String::new();
-    let n = text.len();
+#[cfg(test)]
+mod bwt_test {
+    use super::*;
+
+    #[test]
+    fn encode_pt1_banana() {
+        // BWT of "banana$" is "annb$aa"; the sentinel is dropped and its position returned
+
+        let (encoded, index) = encode(b"banana");
+
+        assert_eq!(b"annbaa".to_vec(), encoded);
+        assert_eq!(4, index);
+    }
+
+    #[test]
+    fn encode_decode_pt1_nul() {
+        // Bytes of 0 are ordinary symbols and must not be confused with the sentinel
+
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![0],
+            vec![0, 0, 0, 0],
+            vec![1, 0, 2, 0, 0, 3, 0],
+            b"\0dpql\0program\0".to_vec(),
+            (0..=255).rev().collect(),
+        ];
+
+        for text in inputs {
+            let (encoded, index) = encode(&text);
 
-    // Create tuples of (char, i)
-    let mut v: Vec<[i32; 2]> = Vec::new();
-    for (i, c) in text.chars().enumerate() {
-        v.push([c as i32, i as i32]);
+            assert_eq!(text.len(), encoded.len());
+            assert_eq!(text, decode(&encoded, index).unwrap());
+        }
     }
 
-    // dbg!(&v.len());
+    #[test]
+    fn decode_pt1_index() {
+        // The primary index may point one past the last byte but no further, and never at row 0
 
-    // Radix-sort the tuples
-    radix_sort(&mut v);
+        assert_eq!(Err(Error::BwtIndexOutOfRange { index: 4, len: 3 }), decode(b"abc", 4));
+        assert_eq!(Err(Error::BwtIndexOutOfRange { index: 0, len: 3 }), decode(b"abc", 0));
+    }
 
-    // dbg!(&v);
+    // naive_suffix_array function sorts the suffixes directly, as a reference
 
-    // Inverse BWT
-    let mut curr_index = index;
-    // dbg!(n);
-    for _ in 0..v.len() {
-        m.push((v[curr_index][0] as u8) as char);
-        dbg!(v[curr_index][0]);
-        curr_index = v[curr_index][1] as usize;
+    fn naive_suffix_array(text: &[u32]) -> Vec<u32> {
+        let mut sa: Vec<u32> = (0..text.len() as u32).collect();
+        sa.sort_by(|&a, &b| text[a as usize..].cmp(&text[b as usize..]));
+        sa
     }
 
-    // Remove sentinel character
-    m.pop();
+    #[test]
+    fn suffix_array_pt1_differential() {
+        // DC3 and SA-IS must produce identical suffix arrays on random texts over small and large alphabets
+
+        let mut state: u32 = 0x9e37_79b9;
+
+        for &k in &[1, 2, 3, 4, 16, 257] {
+            for len in 0..120 {
+                let text: Vec<u32> = (0..len).map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state % k
+                }).collect();
 
-    return m;
-}
\ No newline at end of file
+                let expected = naive_suffix_array(&text);
+
+                assert_eq!(expected, Dc3.suffix_array(&text, k), "dc3 k={} text={:?}", k, text);
+                assert_eq!(expected, crate::compressor::sais::SaIs.suffix_array(&text, k), "sa-is k={} text={:?}", k, text);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_with_pt1_algorithms() {
+        // The BWT does not depend on the suffix array algorithm selected at runtime
+
+        let text = b"mississippi\0banana\0\0abracadabra".repeat(20);
+        let expected = encode_with(&text, &SuffixAlgorithm::Dc3);
+
+        assert_eq!(expected, encode_with(&text, &SuffixAlgorithm::SaIs));
+        assert_eq!(text, decode(&expected.0, expected.1).unwrap());
+    }
+}
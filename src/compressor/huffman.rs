@@ -1,7 +1,9 @@
 // Huffman Encoding and Encoding: Paolo Estavillo
 use std::{collections::{HashMap, BinaryHeap}, cmp::Ordering};
+use crate::error::{Error, Result};
+use super::bitio::{BitReader, BitWriter};
 
-fn get_frequency_arr(text: &Vec<u8>) -> Vec<(u8, i32)> {
+fn get_frequency_arr(text: &[u16]) -> Vec<(u16, i32)> {
 
     // Build frequency map
     let mut char_map: Vec<i32> = vec![0; 256 + 2];
@@ -10,13 +12,13 @@ fn get_frequency_arr(text: &Vec<u8>) -> Vec<(u8, i32)> {
     }
 
     // Store into a vector
-    let mut v: Vec<(u8, i32)> = Vec::new();
+    let mut v: Vec<(u16, i32)> = Vec::new();
     for (i, val) in char_map.iter().enumerate() {
         if char_map[i] == 0 {
             continue;
         }
 
-        v.push((i as u8, *val));
+        v.push((u16::try_from(i).expect("the frequency map has 258 symbols"), *val));
     }
 
     // Sort by frequency
@@ -39,7 +41,7 @@ enum HNodeType {
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 struct HNode {
-    node_val: u8,
+    node_val: u16,
     node_type: HNodeType,
     freq: i32,
     l_child: Option<Box<HNode>>,
@@ -74,7 +76,7 @@ impl PartialOrd for HNode {
     }
 }
 
-fn build_htree(frequency_arr: &Vec<(u8, i32)>) -> HNode {
+fn build_htree(frequency_arr: &Vec<(u16, i32)>) -> HNode {
 
     // Initialize leaf nodes
     let mut pq: BinaryHeap<HNode> = BinaryHeap::new();
@@ -108,7 +110,7 @@ fn build_htree(frequency_arr: &Vec<(u8, i32)>) -> HNode {
         };
 
         let new_node = HNode {
-            node_val: 0_u8,
+            node_val: 0_u16,
             node_type: HNodeType::Parent,
             freq: new_freq,
             l_child: Some(Box::new(left_child)),
@@ -122,70 +124,45 @@ fn build_htree(frequency_arr: &Vec<(u8, i32)>) -> HNode {
 
 }
 
-fn build_codebook (htree_node: &HNode, codeword_stack: String, codeword_map: &mut HashMap<u8, String>) {
-    if (*htree_node).node_type == HNodeType::Leaf {
+fn build_codebook (htree_node: &HNode, codeword_stack: String, codeword_map: &mut HashMap<u16, String>) {
+    if htree_node.node_type == HNodeType::Leaf {
         // Leaf node reached
         if codeword_stack.is_empty() {
             // Edge case: Only one node in huffman tree
-            codeword_map.insert((*htree_node).node_val, String::from("0"));
+            codeword_map.insert(htree_node.node_val, String::from("0"));
         } else {
-            codeword_map.insert((*htree_node).node_val, codeword_stack);
+            codeword_map.insert(htree_node.node_val, codeword_stack);
         }
     } else {
         // Go to left child
         if let Some(left_child) = &(htree_node.l_child) {
-            build_codebook(&(**left_child),
+            build_codebook(left_child,
                            format!("{}0", &codeword_stack),
                            codeword_map);
         }
 
         // Go to right child
         if let Some(right_child) = &(htree_node.r_child) {
-            build_codebook(&(**right_child), 
+            build_codebook(right_child, 
                            format!("{}1", &codeword_stack),
                            codeword_map);
         }
     }
 }
 
-fn get_encoded_bits(codeword_map: &HashMap<u8, String>, message: &Vec<u8>) -> Vec<u8> {
-    let mut bits: Vec<u8> = Vec::new();
+fn write_encoded_bits(codeword_map: &HashMap<u16, (u128, u8)>, message: &[u16], writer: &mut BitWriter) {
     for &num in message {
-        let s = codeword_map.get(&num).unwrap();
-        for c in s.chars() {
-            bits.push(
-                match c {
-                    '1' => 1,
-                     _ => 0,
-                }
-            );
+        let &(code, len) = codeword_map.get(&num).unwrap();
+        for shift in (0..len).rev() {
+            writer.write_bit((code >> shift) & 1 == 1);
         }
     }
-
-    return bits;
-}
-
-fn get_bit_str(n: i128, l: u8) -> String {
-
-    let mut v: String = String::new();
-    for i in 0..l {
-        let b = if (n & (1 << i)) != 0 {
-            '1'
-        } else {
-            '0'
-        };
-        v.push(b);
-    }
-
-    v = v.chars().rev().collect();
-
-    return v;
 }
 
-fn get_canon_freqs(cm: &HashMap<u8, String>) -> Vec<u8> {
+fn get_canon_freqs(cm: &HashMap<u16, String>) -> Vec<u8> {
 
-    let mut v: Vec<(u8, String)> = Vec::new();
-    let mut max_key: u8 = 0;
+    let mut v: Vec<(u16, String)> = Vec::new();
+    let mut max_key: u16 = 0;
     for (c, val) in cm {
         v.push((*c, (*val).clone()));
         max_key = max_key.max(*c);
@@ -205,14 +182,14 @@ fn get_canon_freqs(cm: &HashMap<u8, String>) -> Vec<u8> {
     return ret;
 }
 
-fn reconstruct_canon_cb(canon_freqs: &Vec<u8>) -> HashMap<u8, String> {
-    let mut v: Vec<(u8, u8)> = Vec::new();
+fn reconstruct_canon_cb(canon_freqs: &[u8]) -> HashMap<u16, (u128, u8)> {
+    let mut v: Vec<(u16, u8)> = Vec::new();
     for (i, &val) in canon_freqs.iter().enumerate() {
         if val == 0 { // Only consider at non-zero values
             continue;
         }
 
-        v.push((i as u8, val));
+        v.push((u16::try_from(i).expect("encode builds codebooks of at most 258 symbols"), val));
     }
 
     // Sort by frequencies
@@ -224,8 +201,8 @@ fn reconstruct_canon_cb(canon_freqs: &Vec<u8>) -> HashMap<u8, String> {
         }
     });
 
-    // Build canonical codebook
-    let mut ret: HashMap<u8, String> = HashMap::new();
+    // Build canonical codebook of (codeword, length) pairs
+    let mut ret: HashMap<u16, (u128, u8)> = HashMap::new();
     let mut l: u8 = 0;
     let mut c_canon: i128 = -1;
     for (c, f) in &v {
@@ -235,56 +212,446 @@ fn reconstruct_canon_cb(canon_freqs: &Vec<u8>) -> HashMap<u8, String> {
             l += 1;
         }
 
-        ret.insert(*c, get_bit_str(c_canon, l));
+        ret.insert(*c, (c_canon as u128, l));
     }
 
     return ret;
 }
 
-pub fn encode(text: &Vec<u8>) -> (Vec <u8>, Vec<u8>) {
+// Default maximum codeword length of encode
+pub const MAX_CODE_LEN: u8 = 20;
+
+// Largest alphabet a codebook can describe, as its size is written in 16 bits; symbol 65535 is never used
+pub const MAX_SYMBOLS: usize = u16::MAX as usize;
+
+// limit_code_lengths function computes optimal code lengths of at most max_len bits with the package-merge
+// algorithm; frequency_arr must be sorted by frequency as returned by get_frequency_arr
+
+fn limit_code_lengths(frequency_arr: &[(u16, i32)], max_len: u8) -> Vec<u8> {
+    let n = frequency_arr.len();
+    if n <= 1 {
+        return vec![1; n];
+    }
+
+    // Every item is a weight and the number of times it contains each symbol
+    let leaves: Vec<(u64, Vec<u8>)> = frequency_arr.iter().enumerate()
+        .map(|(i, &(_, freq))| {
+            let mut counts: Vec<u8> = vec![0; n];
+            counts[i] = 1;
+            (freq as u64, counts)
+        })
+        .collect();
+
+    let mut items = leaves.clone();
+    for _ in 1..max_len {
+        // Package adjacent pairs of the cheapest items, then merge the packages with the leaves
+        let packages: Vec<(u64, Vec<u8>)> = items.chunks_exact(2)
+            .map(|pair| {
+                let counts = pair[0].1.iter().zip(&pair[1].1).map(|(a, b)| a + b).collect();
+                (pair[0].0 + pair[1].0, counts)
+            })
+            .collect();
+
+        items = leaves.iter().cloned().chain(packages).collect();
+        items.sort_by_key(|item| item.0);
+    }
+
+    // The 2n - 2 cheapest items select the code lengths
+    let mut lens: Vec<u8> = vec![0; n];
+    for (_, counts) in &items[..2 * n - 2] {
+        for (len, count) in lens.iter_mut().zip(counts) {
+            *len += count;
+        }
+    }
+
+    return lens;
+}
+
+// validate_codebook function checks that the code lengths satisfy the Kraft inequality, so that canonical
+// codewords can be assigned to every symbol, and that the alphabet has at most MAX_SYMBOLS symbols
+
+pub fn validate_codebook(canon_freqs: &[u8]) -> Result<()> {
+    if canon_freqs.len() > MAX_SYMBOLS {
+        return Err(Error::CodebookViolation);
+    }
+
+    // Canonical codes are built in an i128 cursor
+    let max_len = canon_freqs.iter().copied().max().unwrap_or(0);
+    if max_len >= 128 {
+        return Err(Error::CodebookViolation);
+    }
+
+    // Sum of 2^-len, scaled by 2^max_len; it is checked as it grows because 2^128 does not fit in a u128
+    let limit = 1u128 << max_len;
+    let mut kraft_sum: u128 = 0;
+    for &len in canon_freqs {
+        if len != 0 {
+            kraft_sum = kraft_sum.checked_add(1u128 << (max_len - len))
+                .filter(|&sum| sum <= limit)
+                .ok_or(Error::CodebookViolation)?;
+        }
+    }
+
+    return Ok(());
+}
+
+/* write_codebook function serializes the code lengths in a self-describing form: the number of symbols
+(16 bits), then for every symbol a used bit, and for every used symbol its length delta-coded from the
+previous used length (starting at 0): 10 adds one, 11 subtracts one and 0 ends the delta */
+
+pub fn write_codebook(canon_freqs: &[u8], writer: &mut BitWriter) {
+    let count: u16 = canon_freqs.len().try_into().expect("validate_codebook limits the alphabet to MAX_SYMBOLS");
+    writer.write_bits(u64::from(count), 16);
+
+    let mut prev: u8 = 0;
+    for &len in canon_freqs {
+        writer.write_bit(len != 0);
+        if len == 0 {
+            continue;
+        }
+
+        while prev < len {
+            writer.write_bits(0b10, 2);
+            prev += 1;
+        }
+
+        while prev > len {
+            writer.write_bits(0b11, 2);
+            prev -= 1;
+        }
+
+        writer.write_bit(false);
+    }
+}
+
+// read_codebook function reads code lengths written by write_codebook and validates them
+
+pub fn read_codebook(reader: &mut BitReader) -> Result<Vec<u8>> {
+    let count = reader.read_bits(16).ok_or(Error::CodebookViolation)?;
+
+    let mut canon_freqs: Vec<u8> = Vec::new();
+    let mut len: u8 = 0;
+    for _ in 0..count {
+        if !reader.read_bit().ok_or(Error::CodebookViolation)? {
+            canon_freqs.push(0);
+            continue;
+        }
+
+        while reader.read_bit().ok_or(Error::CodebookViolation)? {
+            if reader.read_bit().ok_or(Error::CodebookViolation)? {
+                len = len.checked_sub(1).ok_or(Error::CodebookViolation)?;
+            } else {
+                len += 1;
+            }
+
+            // Canonical codes are built in an i128 cursor
+            if len >= 128 {
+                return Err(Error::CodebookViolation);
+            }
+        }
+
+        // Used symbols need a codeword
+        if len == 0 {
+            return Err(Error::CodebookViolation);
+        }
+
+        canon_freqs.push(len);
+    }
+
+    validate_codebook(&canon_freqs)?;
+
+    return Ok(canon_freqs);
+}
+
+// encode function Huffman codes the text into the writer with codewords of at most MAX_CODE_LEN bits and
+// returns the codeword length of every symbol; symbols are RLE output, so they must be below 256 + 2
+
+pub fn encode(text: &[u16], writer: &mut BitWriter) -> Vec<u8> {
+    return encode_with_limit(text, writer, MAX_CODE_LEN);
+}
+
+// encode_with_limit function is encode with codewords of at most max_len bits; max_len must be below 128 and
+// long enough to give every distinct symbol of the text its own codeword, and every symbol below MAX_SYMBOLS
+
+pub fn encode_with_limit(text: &[u16], writer: &mut BitWriter, max_len: u8) -> Vec<u8> {
 
     if text.is_empty() {
-        return (vec![], vec![0; 10]);
+        return vec![0; 10];
     }
 
-    let v = get_frequency_arr(&text);
+    let v = get_frequency_arr(text);
+    assert!(max_len < 128, "codeword lengths must be below 128 bits");
+    assert!((v.len() - 1).checked_shr(u32::from(max_len)).unwrap_or(0) == 0, "{} bit codewords cannot hold {} symbols", max_len, v.len());
+
     let ht = build_htree(&v);
-    let mut cm: HashMap<u8, String> = HashMap::new();
+    let mut cm: HashMap<u16, String> = HashMap::new();
     build_codebook(&ht, String::from(""), &mut cm);
-    let canon_freqs = get_canon_freqs(&cm);
+    let mut canon_freqs = get_canon_freqs(&cm);
+    assert!(canon_freqs.len() <= MAX_SYMBOLS, "codebooks cannot hold symbol {}", u16::MAX);
+
+    // Replace the Huffman tree lengths when the tree is deeper than the limit
+    if canon_freqs.iter().copied().max().unwrap_or(0) > max_len {
+        for (&(c, _), len) in v.iter().zip(limit_code_lengths(&v, max_len)) {
+            canon_freqs[c as usize] = len;
+        }
+    }
+
+    debug_assert!(validate_codebook(&canon_freqs).is_ok());
+
     let canon_cm = reconstruct_canon_cb(&canon_freqs);
-    let encoded_bits = get_encoded_bits(&canon_cm, &text);
+    write_encoded_bits(&canon_cm, text, writer);
+
+    return canon_freqs;
+}
+
+// Number of bits looked up at once by the decoding table
+const TABLE_BITS: u8 = 10;
+
+// Canonical Huffman decoder: a lookup table resolves every codeword of at most table_bits bits in one step,
+// longer codewords are decoded bit by bit with the first-code and count of every length
+
+struct CanonDecoder {
+    table_bits: u8,
+    table: Vec<(u16, u8)>,         // (symbol, codeword length) of every table_bits prefix; length 0 if none fits
+    first_code: Vec<u128>,         // First canonical codeword of every length
+    count: Vec<u128>,              // Number of codewords of every length
+    first_index: Vec<usize>,       // Index in symbols of the first codeword of every length
+    symbols: Vec<u16>,             // Symbols sorted by (codeword length, symbol)
+}
+
+impl CanonDecoder {
+    fn new(canon_freqs: &[u8]) -> Result<CanonDecoder> {
+        validate_codebook(canon_freqs)?;
+        let max_len = canon_freqs.iter().copied().max().unwrap_or(0);
+
+        let mut symbols: Vec<(u8, u16)> = Vec::new();
+        for (i, &len) in canon_freqs.iter().enumerate() {
+            if len != 0 {
+                symbols.push((len, u16::try_from(i).expect("validate_codebook limits the alphabet to MAX_SYMBOLS")));
+            }
+        }
+        symbols.sort();
+
+        // First codeword and symbol index of every length, as in reconstruct_canon_cb
+        let lens = max_len as usize + 1;
+        let mut count: Vec<u128> = vec![0; lens];
+        for &(len, _) in &symbols {
+            count[len as usize] += 1;
+        }
+
+        let mut first_code: Vec<u128> = vec![0; lens];
+        let mut first_index: Vec<usize> = vec![0; lens];
+        let mut code: u128 = 0;
+        let mut index: usize = 0;
+        for len in 1..lens {
+            code <<= 1;
+            first_code[len] = code;
+            first_index[len] = index;
+            code += count[len];
+            index += count[len] as usize;
+        }
+
+        // Fill the lookup table with every codeword that fits in table_bits bits
+        let table_bits = max_len.min(TABLE_BITS);
+        let mut table: Vec<(u16, u8)> = vec![(0, 0); 1 << table_bits];
+        let mut next_code = first_code.clone();
+        for &(len, symbol) in &symbols {
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+
+            if len <= table_bits {
+                let shift = table_bits - len;
+                let start = (code as usize) << shift;
+                table[start..start + (1 << shift)].fill((symbol, len));
+            }
+        }
+
+        let symbols = symbols.iter().map(|&(_, symbol)| symbol).collect();
+
+        return Ok(CanonDecoder { table_bits, table, first_code, count, first_index, symbols });
+    }
+
+    // decode_symbol function decodes the next codeword of the reader
+
+    fn decode_symbol(&self, reader: &mut BitReader) -> Result<u16> {
+        let (symbol, len) = self.table[reader.peek_bits(self.table_bits as u32) as usize];
+        if len != 0 && len as usize <= reader.remaining() {
+            reader.skip_bits(len as usize);
+            return Ok(symbol);
+        }
+
+        // Longer codeword, or too few bits left for the table: walk the lengths one bit at a time
+        let mut code: u128 = 0;
+        for len in 1..self.first_code.len() {
+            let bit = reader.read_bit().ok_or(Error::CodebookViolation)?;
+            code = (code << 1) | u128::from(bit);
+
+            let offset = code.wrapping_sub(self.first_code[len]);
+            if code >= self.first_code[len] && offset < self.count[len] {
+                return Ok(self.symbols[self.first_index[len] + offset as usize]);
+            }
+        }
 
-    return (encoded_bits, canon_freqs);
+        // No codeword is this long
+        return Err(Error::CodebookViolation);
+    }
 }
 
-pub fn decode(data: &Vec<u8>, canon_freqs: &Vec<u8>) -> Vec<u8> {
+pub fn decode(reader: &mut BitReader, canon_freqs: &[u8]) -> Result<Vec<u16>> {
 
-    for bit in data {
-        assert!(*bit == 0 || *bit == 1);
+    let decoder = CanonDecoder::new(canon_freqs)?;
+
+    let mut ret: Vec<u16> = Vec::new();
+    while reader.remaining() > 0 {
+        ret.push(decoder.decode_symbol(reader)?);
     }
 
-    let canon_cb = reconstruct_canon_cb(canon_freqs);
-    // Reverse canonized codebook from string to u8
-    let mut rev_canon_cb: HashMap<String, u8> = HashMap::new();
-    for (key, value) in &canon_cb {
-        rev_canon_cb.insert((*value).clone(), *key);
+    return Ok(ret);
+}
+
+#[cfg(test)]
+mod huffman_test {
+    use super::*;
+
+    // round_trip function encodes text and decodes it again with the table-driven decoder
+
+    fn round_trip(text: &[u16]) -> Vec<u16> {
+        let mut writer = BitWriter::new();
+        let canon_freqs = encode(text, &mut writer);
+        let padding = writer.padding();
+        let bytes = writer.into_bytes();
+
+        return decode(&mut BitReader::with_padding(&bytes, padding), &canon_freqs).unwrap();
     }
 
-    let mut s = String::new();
-    let mut ret: Vec<u8> = Vec::new();
-    // For every bit from left to right
-    for bit in data {
-        // Push the bit in 1
-        s.push((bit + '0' as u8) as char);
+    #[test]
+    fn encode_decode_pt1_lengths() {
+        // Skewed frequencies give codewords both shorter and longer than the lookup table
 
-        // Check if s contains a mapping
-        if rev_canon_cb.contains_key(&s) {
-            let symb = rev_canon_cb.get(&s).copied().unwrap();
-            ret.push(symb);
-            s.clear();
+        let mut text: Vec<u16> = Vec::new();
+        for (i, symbol) in (0..40u16).enumerate() {
+            text.extend(std::iter::repeat_n(symbol, 1 << (i / 2).min(16)));
         }
+
+        assert_eq!(text, round_trip(&text));
+        assert_eq!(vec![3, 3, 3], round_trip(&[3, 3, 3]));
     }
 
-    return ret;
-}
\ No newline at end of file
+    #[test]
+    fn decode_pt1_invalid() {
+        // Over-subscribed code lengths and bits that are not a codeword are rejected
+
+        let mut reader = BitReader::new(&[0]);
+        assert_eq!(Err(Error::CodebookViolation), decode(&mut reader, &[1, 1, 1, 0, 0, 0, 0, 0, 0, 0]));
+
+        let mut reader = BitReader::new(&[0x80]);
+        assert_eq!(Err(Error::CodebookViolation), decode(&mut reader, &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn limit_code_lengths_pt1_fibonacci() {
+        // Fibonacci frequencies give a Huffman tree as deep as the alphabet; package-merge keeps the limit
+
+        let mut frequency_arr: Vec<(u16, i32)> = vec![(0, 1), (1, 1)];
+        for i in 2..40u16 {
+            let freq = frequency_arr[i as usize - 1].1 + frequency_arr[i as usize - 2].1;
+            frequency_arr.push((i, freq));
+        }
+
+        for max_len in [6, 15, 20] {
+            let lens = limit_code_lengths(&frequency_arr, max_len);
+
+            assert_eq!(max_len, lens.iter().copied().max().unwrap());
+            assert!(validate_codebook(&lens).is_ok());
+
+            // The code is complete: the Kraft sum is exactly one
+            let kraft_sum: u64 = lens.iter().map(|&len| 1u64 << (max_len - len)).sum();
+            assert_eq!(1u64 << max_len, kraft_sum);
+        }
+    }
+
+    #[test]
+    fn encode_with_limit_pt1_round_trip() {
+        // A short limit changes the codebook but not the decoded text
+
+        let mut text: Vec<u16> = Vec::new();
+        for (i, symbol) in (0..40u16).enumerate() {
+            text.extend(std::iter::repeat_n(symbol, 1 << (i / 2).min(16)));
+        }
+
+        let mut writer = BitWriter::new();
+        let canon_freqs = encode_with_limit(&text, &mut writer, 7);
+        let padding = writer.padding();
+        let bytes = writer.into_bytes();
+
+        assert_eq!(7, canon_freqs.iter().copied().max().unwrap());
+        assert_eq!(text, decode(&mut BitReader::with_padding(&bytes, padding), &canon_freqs).unwrap());
+
+        // Limits wider than a usize leave the Huffman tree lengths alone
+        let mut writer = BitWriter::new();
+        assert_eq!(vec![0, 2, 2, 1, 0, 0, 0, 0, 0, 0], encode_with_limit(&[1, 2, 3, 3], &mut writer, 64));
+        assert_eq!(vec![1, 1], encode_with_limit(&[0, 1], &mut BitWriter::new(), 127)[..2]);
+    }
+
+    #[test]
+    fn validate_codebook_pt1_kraft() {
+        // Complete, incomplete and over-subscribed code lengths
+
+        assert!(validate_codebook(&[1, 2, 3, 3]).is_ok());
+        assert!(validate_codebook(&[0, 2, 0, 3]).is_ok());
+        assert!(validate_codebook(&[]).is_ok());
+        assert_eq!(Err(Error::CodebookViolation), validate_codebook(&[1, 2, 2, 3]));
+        assert_eq!(Err(Error::CodebookViolation), validate_codebook(&[128]));
+
+        // Four codewords of length 1 would carry the sum to 2^128 with 127-bit codes
+        assert_eq!(Err(Error::CodebookViolation), validate_codebook(&[1, 1, 1, 1, 127]));
+
+        // Alphabets whose size does not fit in the 16-bit count of write_codebook are rejected
+        let mut wide = vec![0; MAX_SYMBOLS + 1];
+        wide[MAX_SYMBOLS] = 1;
+        assert!(validate_codebook(&wide[1..]).is_ok());
+        assert_eq!(Err(Error::CodebookViolation), validate_codebook(&wide));
+
+        // The widest valid codebook is written and read back without a panic
+        let mut writer = BitWriter::new();
+        write_codebook(&wide[1..], &mut writer);
+        let bytes = writer.into_bytes();
+        assert_eq!(Ok(wide[1..].to_vec()), read_codebook(&mut BitReader::new(&bytes)));
+    }
+
+    #[test]
+    fn write_read_codebook_pt1_sizes() {
+        // Codebooks of any alphabet size come back unchanged and take fewer bits than a byte per symbol
+
+        let mut byte_codebook: Vec<u8> = vec![0; 258];
+        for (i, len) in byte_codebook.iter_mut().enumerate().skip(2) {
+            *len = if i < 4 { 2 } else if i < 8 { 4 } else { 10 };
+        }
+
+        for canon_freqs in [vec![], vec![0; 10], vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0], vec![2, 3, 1, 3], byte_codebook] {
+            let mut writer = BitWriter::new();
+            write_codebook(&canon_freqs, &mut writer);
+
+            assert!(writer.bit_len() <= 16 + 8 * canon_freqs.len());
+
+            let bytes = writer.into_bytes();
+            assert_eq!(Ok(canon_freqs), read_codebook(&mut BitReader::new(&bytes)));
+        }
+    }
+
+    #[test]
+    fn read_codebook_pt1_invalid() {
+        // Truncated codebooks, used symbols of length zero and over-subscribed lengths are rejected
+
+        assert_eq!(Err(Error::CodebookViolation), read_codebook(&mut BitReader::new(&[0, 2, 0x80])));
+        assert_eq!(Err(Error::CodebookViolation), read_codebook(&mut BitReader::new(&[0, 1])));
+
+        let mut writer = BitWriter::new();
+        writer.write_bits(3, 16);
+        writer.write_bits(0b1100, 4);    // used, +1, end
+        writer.write_bits(0b1_0, 2);
+        writer.write_bits(0b1_0, 2);
+        let bytes = writer.into_bytes();
+        assert_eq!(Err(Error::CodebookViolation), read_codebook(&mut BitReader::new(&bytes)));
+    }
+}
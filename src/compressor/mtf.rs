@@ -1,38 +1,39 @@
 // Move to Front Encoding and Decoding: Paolo Estavillo
+use crate::error::{Error, Result};
 
-fn search(alphabet: &String, c: char) -> usize {
-    assert!(alphabet.contains(c), "character not found in alphabet");
-
-    alphabet.find(c).unwrap()
+fn search(alphabet: &[char], c: char) -> Result<usize> {
+    alphabet.iter().position(|&a| a == c).ok_or(Error::SymbolNotInAlphabet)
 }
 
-pub fn encode(text: &String, alphabet: &String) -> Vec<u8> {
-    let mut s: Vec<u8> = Vec::new();
-    let mut current_alphabet = alphabet.clone();
+pub fn encode(text: &str, alphabet: &str) -> Result<Vec<u16>> {
+    let mut s: Vec<u16> = Vec::new();
+    let mut current_alphabet: Vec<char> = alphabet.chars().collect();
     
     for c in text.chars() {
         // Get position
-        let pos = search(&current_alphabet, c);
+        let pos = search(&current_alphabet, c)?;
 
         // Push to s
-        s.push(pos as u8);
+        s.push(pos as u16);
 
         // move to front
         current_alphabet.remove(pos);
         current_alphabet.insert(0, c);
     }
 
-    return s;
+    return Ok(s);
 }
 
-pub fn decode(data: &Vec<u8>, alphabet: &String) -> String {
+pub fn decode(data: &[u16], alphabet: &str) -> Result<String> {
     let mut s = String::new();
-    let current_alphabet = alphabet.clone();
-    let mut current_alphabet: Vec<char> = current_alphabet.chars().collect();
+    let mut current_alphabet: Vec<char> = alphabet.chars().collect();
 
     for &pos in data {
         // Get character at pos
-        let c = current_alphabet[pos as usize];
+        let c = match current_alphabet.get(pos as usize) {
+            Some(&c) => c,
+            None => return Err(Error::SymbolNotInAlphabet),
+        };
 
         // Push to reconstructed message
         s.push(c);
@@ -42,5 +43,5 @@ pub fn decode(data: &Vec<u8>, alphabet: &String) -> String {
         current_alphabet.insert(0, c);
     }
 
-    return s;
-}
\ No newline at end of file
+    return Ok(s);
+}
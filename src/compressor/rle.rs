@@ -1,4 +1,5 @@
 // Run Length Encoding and Decoding: Paolo Estavillo
+use crate::error::{Error, Result};
 
 fn get_msb(n: i128) -> i128 {
     let mut n = n;
@@ -15,12 +16,12 @@ fn get_msb(n: i128) -> i128 {
     return 1 << (num_bits - 1);
 }
 
-pub fn encode(text: &Vec<u8>) -> Vec<u8> {
-    let mut encoded_sequence: Vec<u8> = Vec::new();
+pub fn encode(text: &[u16]) -> Vec<u16> {
+    let mut encoded_sequence: Vec<u16> = Vec::new();
 
     let mut n_zero: i128 = 0;
     for i in 0..=text.len() {
-        if (i <= text.len() - 1) && (text[i] == 0) {
+        if (i < text.len()) && (text[i] == 0) {
             n_zero += 1;
         } else {
             // Increment n_zero
@@ -29,7 +30,7 @@ pub fn encode(text: &Vec<u8>) -> Vec<u8> {
             // Push each digit from lsb until msb
             let lim = get_msb(n_zero);
             let mut shamt: i128 = 0;
-            while ((1 as i128) << shamt) < lim {
+            while (1_i128 << shamt) < lim {
                 encoded_sequence.push(
                     if (n_zero & (1 << shamt)) == 0 {0} else {1}
                 );
@@ -37,8 +38,8 @@ pub fn encode(text: &Vec<u8>) -> Vec<u8> {
             }
 
             n_zero = 0; // Reset N_zero to 0
-            if i <= text.len() - 1 {
-                encoded_sequence.push(text[i] + 2); // push s_i + 2
+            if i < text.len() {
+                encoded_sequence.push(text[i] + 2); // push s_i + 2; ranks up to 255 become symbols up to 257
             }
         }
     }
@@ -46,7 +47,7 @@ pub fn encode(text: &Vec<u8>) -> Vec<u8> {
     return encoded_sequence;
 }
 
-fn bits_to_n(bit_stack: &Vec<u8>) -> i32 {
+fn bits_to_n(bit_stack: &[u16]) -> i32 {
     let mut ret:i32 = 0;
     for (i, bit) in bit_stack.iter().enumerate() {
         ret |= (*bit as i32) << i;
@@ -54,9 +55,11 @@ fn bits_to_n(bit_stack: &Vec<u8>) -> i32 {
     return ret;
 }
 
-pub fn decode(data: &Vec<u8>) -> Vec<u8> {
-    let mut decoded_sequence: Vec<u8> = Vec::new();
-    let mut n_zero: Vec<u8> = Vec::new();
+// decode function reverses encode and refuses zero runs that would make the output longer than max_len symbols
+
+pub fn decode(data: &[u16], max_len: usize) -> Result<Vec<u16>> {
+    let mut decoded_sequence: Vec<u16> = Vec::new();
+    let mut n_zero: Vec<u16> = Vec::new();
 
     for i in 0..=data.len() {
         if (i < data.len()) && (data[i] == 0 || data[i] == 1) {
@@ -66,12 +69,20 @@ pub fn decode(data: &Vec<u8>) -> Vec<u8> {
             // Push a 1
             n_zero.push(1);
 
+            // Run length must fit in bits_to_n
+            if n_zero.len() >= 32 {
+                return Err(Error::InvalidRunLength);
+            }
+
             // Reverse bits and convert to decimal
-            let mut n_zeros_num = bits_to_n(&n_zero);
-            n_zeros_num -= 1;
-            for _ in 0..n_zeros_num {
-                decoded_sequence.push(0);
+            let n_zeros_num = (bits_to_n(&n_zero) - 1) as usize;
+
+            // A few run symbols can encode billions of zeros, so the run is checked before it is allocated
+            if n_zeros_num > max_len.saturating_sub(decoded_sequence.len()) {
+                return Err(Error::InvalidRunLength);
             }
+
+            decoded_sequence.resize(decoded_sequence.len() + n_zeros_num, 0);
             n_zero.clear();
 
             if i < data.len() {
@@ -80,5 +91,56 @@ pub fn decode(data: &Vec<u8>) -> Vec<u8> {
         }
     }
 
-    return decoded_sequence;
-}
\ No newline at end of file
+    return Ok(decoded_sequence);
+}
+#[cfg(test)]
+mod rle_test {
+    use super::*;
+    use crate::compressor::bitio::{BitReader, BitWriter};
+    use crate::compressor::{huffman, mtf};
+
+    #[test]
+    fn encode_decode_pt1_wide() {
+        // Ranks 254 and 255 become symbols 256 and 257 instead of overflowing
+
+        let ranks: Vec<u16> = vec![255, 0, 0, 0, 254, 1, 0];
+        let encoded = encode(&ranks);
+
+        assert_eq!(vec![257, 0, 0, 256, 3, 0], encoded);
+        assert_eq!(Ok(ranks), decode(&encoded, 7));
+    }
+
+    #[test]
+    fn decode_pt1_long_run() {
+        // Thirty RUNB symbols encode a run of about 2^31 zeros, far beyond the limit
+
+        let encoded = vec![1u16; 30];
+
+        assert_eq!(Err(Error::InvalidRunLength), decode(&encoded, 1 << 20));
+        assert_eq!(Ok(vec![0, 0, 4, 0]), decode(&[1, 6, 0], 4));
+        assert_eq!(Err(Error::InvalidRunLength), decode(&[1, 6, 0], 3));
+    }
+
+    #[test]
+    fn mtf_rle_huffman_pt1_bytes() {
+        // Arbitrary bytes run through the whole MTF, RLE and Huffman chain with a 256-symbol alphabet
+
+        let alphabet: String = (0..=255u8).map(|b| b as char).collect();
+        let text: String = (0..2000u32).map(|i| ((i * i * 31 + i / 7) % 256) as u8 as char).collect();
+
+        let mtf_encoded = mtf::encode(&text, &alphabet).unwrap();
+        let rle_encoded = encode(&mtf_encoded);
+
+        let mut writer = BitWriter::new();
+        let canon_freqs = huffman::encode(&rle_encoded, &mut writer);
+        let padding = writer.padding();
+        let bytes = writer.into_bytes();
+
+        assert_eq!(258, canon_freqs.len());
+
+        let huffman_decoded = huffman::decode(&mut BitReader::with_padding(&bytes, padding), &canon_freqs).unwrap();
+        let mtf_decoded = mtf::decode(&decode(&huffman_decoded, text.len() + 1).unwrap(), &alphabet).unwrap();
+
+        assert_eq!(text, mtf_decoded);
+    }
+}
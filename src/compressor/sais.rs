@@ -0,0 +1,188 @@
+// SA-IS suffix array construction (Nong, Zhang and Chan): induced sorting in O(n) time
+
+use super::bwt::SuffixArray;
+
+const EMPTY: u32 = u32::MAX;
+
+// Suffix array builder using SA-IS; besides the suffix array it keeps a shifted copy of the text, a type flag per
+// symbol and the bucket bounds, and every recursion level adds the reduced string, its suffix array and its LMS
+// positions, each at most half as long as the level above
+
+pub struct SaIs;
+
+impl SuffixArray for SaIs {
+    fn suffix_array(&self, text: &[u32], k: u32) -> Vec<u32> {
+        // Shift the symbols up by one and append a unique smallest sentinel
+        let mut s: Vec<u32> = text.iter().map(|&c| c + 1).collect();
+        s.push(0);
+
+        let mut sa = sais(&s, k as usize + 1);
+
+        // The sentinel suffix always sorts first
+        sa.drain(..1);
+
+        return sa;
+    }
+}
+
+// is_lms function checks if position i is a leftmost S-type position
+
+fn is_lms(t: &[bool], i: usize) -> bool {
+    i > 0 && t[i] && !t[i - 1]
+}
+
+// bucket_bounds function returns the start (or end) index of the bucket of every symbol
+
+fn bucket_bounds(s: &[u32], k: usize, ends: bool) -> Vec<u32> {
+    let mut counts: Vec<u32> = vec![0; k];
+    for &c in s {
+        counts[c as usize] += 1;
+    }
+
+    let mut sum: u32 = 0;
+    for count in counts.iter_mut() {
+        sum += *count;
+        *count = if ends { sum } else { sum - *count };
+    }
+
+    return counts;
+}
+
+// induce function sorts the L-type suffixes from the LMS suffixes, then the S-type suffixes from the L-type suffixes
+
+fn induce(s: &[u32], t: &[bool], sa: &mut [u32], k: usize) {
+    let n = s.len();
+
+    let mut starts = bucket_bounds(s, k, false);
+    for i in 0..n {
+        let j = sa[i];
+
+        if j != EMPTY && j > 0 && !t[j as usize - 1] {
+            let c = s[j as usize - 1] as usize;
+            sa[starts[c] as usize] = j - 1;
+            starts[c] += 1;
+        }
+    }
+
+    let mut ends = bucket_bounds(s, k, true);
+    for i in (0..n).rev() {
+        let j = sa[i];
+
+        if j != EMPTY && j > 0 && t[j as usize - 1] {
+            let c = s[j as usize - 1] as usize;
+            ends[c] -= 1;
+            sa[ends[c] as usize] = j - 1;
+        }
+    }
+}
+
+// lms_equal function checks if the LMS substrings starting at a and b are identical
+
+fn lms_equal(s: &[u32], t: &[bool], a: usize, b: usize) -> bool {
+    let n = s.len();
+
+    // The sentinel substring is unique
+    if a == n - 1 || b == n - 1 {
+        return false;
+    }
+
+    let mut d: usize = 0;
+
+    loop {
+        if s[a + d] != s[b + d] || t[a + d] != t[b + d] {
+            return false;
+        }
+
+        if d > 0 {
+            let a_end = is_lms(t, a + d);
+            let b_end = is_lms(t, b + d);
+
+            if a_end || b_end {
+                return a_end && b_end;
+            }
+        }
+
+        d += 1;
+    }
+}
+
+// sais function returns the suffix array of s, whose last symbol must be a unique 0 and whose symbols are below k
+
+fn sais(s: &[u32], k: usize) -> Vec<u32> {
+    let n = s.len();
+
+    if n == 1 {
+        return vec![0];
+    }
+
+    // Classify every suffix as S-type (true) or L-type (false)
+    let mut t: Vec<bool> = vec![false; n];
+    t[n - 1] = true;
+    for i in (0..n - 1).rev() {
+        t[i] = s[i] < s[i + 1] || (s[i] == s[i + 1] && t[i + 1]);
+    }
+
+    // Step 1: place the LMS positions at the ends of their buckets and induce to sort the LMS substrings
+    let mut sa: Vec<u32> = vec![EMPTY; n];
+    let mut ends = bucket_bounds(s, k, true);
+    for (i, &c) in s.iter().enumerate().skip(1) {
+        if is_lms(&t, i) {
+            ends[c as usize] -= 1;
+            sa[ends[c as usize] as usize] = i as u32;
+        }
+    }
+    induce(s, &t, &mut sa, k);
+
+    // Step 2: name the sorted LMS substrings, compacting them to the front of sa
+    let mut n1: usize = 0;
+    for i in 0..n {
+        if is_lms(&t, sa[i] as usize) {
+            sa[n1] = sa[i];
+            n1 += 1;
+        }
+    }
+
+    // Names are stored at sa[n1 + pos / 2]; LMS positions are at least two apart
+    sa[n1..].fill(EMPTY);
+    let mut name: u32 = 0;
+    let mut prev: usize = usize::MAX;
+    for i in 0..n1 {
+        let pos = sa[i] as usize;
+
+        if prev == usize::MAX || !lms_equal(s, &t, prev, pos) {
+            name += 1;
+        }
+
+        prev = pos;
+        sa[n1 + pos / 2] = name - 1;
+    }
+
+    // Reduced string of the names in text order
+    let s1: Vec<u32> = sa[n1..].iter().copied().filter(|&c| c != EMPTY).collect();
+
+    // Sort the LMS suffixes, recursing only when some names repeat
+    let sa1: Vec<u32> = if (name as usize) < n1 {
+        sais(&s1, name as usize)
+    } else {
+        let mut sa1: Vec<u32> = vec![0; n1];
+        for (i, &c) in s1.iter().enumerate() {
+            sa1[c as usize] = i as u32;
+        }
+        sa1
+    };
+
+    // Step 3: place the sorted LMS suffixes at the ends of their buckets and induce the full suffix array
+    let lms_positions: Vec<u32> = (1..n).filter(|&i| is_lms(&t, i)).map(|i| i as u32).collect();
+
+    sa.fill(EMPTY);
+    let mut ends = bucket_bounds(s, k, true);
+    for i in (0..n1).rev() {
+        let j = lms_positions[sa1[i] as usize];
+        let c = s[j as usize] as usize;
+        ends[c] -= 1;
+        sa[ends[c] as usize] = j;
+    }
+    induce(s, &t, &mut sa, k);
+
+    return sa;
+}
@@ -1,21 +1,28 @@
-/* write function increments or decrements the content of memory cell index 0 until it is equal
-to the ASCII code of the character in the text; then push it to the output queue */
+use crate::error::{Error, Result};
 
-pub fn write(text: &String) -> String {
+/* write function converts the UTF-8 bytes of the text to a diropql program; see write_bytes */
+
+pub fn write(text: &str) -> String {
+    return write_bytes(text.as_bytes());
+}
+
+/* write_bytes function increments or decrements the content of memory cell index 0 until it is equal
+to the value of the byte in the data; then push it to the output queue */
+
+pub fn write_bytes(data: &[u8]) -> String {
     let mut diropql_program = String::new();    // diropql program code
     let mut mp_0: u8 = 0;   // Content of memory cell index 0
 
-    for c in text.chars() {
-        let ascii_code = c as u8;   // ASCII code of the character
+    for &byte in data {
 
-        while mp_0 != ascii_code {  // Loop until content of memory cell index 0 is equal to ASCII code of the character
+        while mp_0 != byte {  // Loop until content of memory cell index 0 is equal to value of the byte
 
-            if mp_0 > ascii_code {
+            if mp_0 > byte {
 
-                if mp_0 - ascii_code > 128 {    // More efficient to add if this is the case, e.g. 255 -> 4 (5 moves)
+                if mp_0 - byte > 128 {    // More efficient to add if this is the case, e.g. 255 -> 4 (5 moves)
                         
                     if mp_0 < 255 {
-                        mp_0 = mp_0 + 1;
+                        mp_0 += 1;
                     }
 
                     else {
@@ -26,17 +33,17 @@ pub fn write(text: &String) -> String {
                 }
 
                 else {
-                    mp_0 = mp_0 - 1;    // More efficient to subtract if this is the case, e.g. 255 -> 250 (5 moves)
+                    mp_0 -= 1;    // More efficient to subtract if this is the case, e.g. 255 -> 250 (5 moves)
                     diropql_program.push('d');  // d to diropql program code
                 }
             }
 
             else {
 
-                 if ascii_code - mp_0 > 128 {    // More efficient to subtract if this is the case, e.g. 4 -> 255 (5 moves)
+                 if byte - mp_0 > 128 {    // More efficient to subtract if this is the case, e.g. 4 -> 255 (5 moves)
                         
                     if mp_0 > 0 {
-                        mp_0 = mp_0 - 1;
+                        mp_0 -= 1;
                     }
 
                     else {
@@ -47,140 +54,101 @@ pub fn write(text: &String) -> String {
                 }
 
                 else {
-                    mp_0 = mp_0 + 1;    // More efficient to add if this is the case, e.g. 250 -> 255 (5 moves)
+                    mp_0 += 1;    // More efficient to add if this is the case, e.g. 250 -> 255 (5 moves)
                     diropql_program.push('i');  // i to diropql program code
                 }
             }
         }
 
-        // o to diropql program code once content of memory cell index 0 equals ASCII code of the character
+        // o to diropql program code once content of memory cell index 0 equals value of the byte
         diropql_program.push('o');  
     }
 
     return diropql_program;
 }
 
-// read function reads a diropql program code and returns the text
-
-pub fn read(prog: &String) -> String {
-    let mut memory_cells: Vec<u8> = vec![0; 10000]; // Initialize 10,000 memory cells to 0
-
-    // Initialize memory pointer, instruction pointer, and output queue
-    let mut mp: usize = 0;
-    let mut ip: usize = 0;
-    let mut oq: Vec<u8> = Vec::new();
+// read function reads a diropql program code and returns its output as UTF-8 text
 
-    // Record indices of matching p and q commands in a vector
-    let mut pq_index: Vec<(usize, usize)> = Vec::new();
+pub fn read(prog: &str) -> Result<String> {
+    let output = read_bytes(prog)?;
 
-    // Algorithm to pair the indices of the p and q commands
-    let mut p_indices: Vec<usize> = Vec::new();
-
-    for (index, c) in prog.chars().enumerate() {
-
-        if c == 'p' {
-            p_indices.push(index);
-        } 
-        
-        else if c == 'q' {
+    return String::from_utf8(output).map_err(|_| Error::InvalidUtf8);
+}
 
-            if let Some(p_index) = p_indices.pop() {
-                pq_index.push((p_index, index));
-            }
-        }
-    }
+// read_bytes function reads a diropql program code and returns the bytes in its output queue; the program is
+// compiled to bytecode first, so every command is parsed once, and its common loops run as single operations
 
-    // Read each command in the diropql program code
-    while ip != prog.len() {
-        let c = prog.chars().nth(ip);
+pub fn read_bytes(prog: &str) -> Result<Vec<u8>> {
+    return bytecode::run(&optimize::optimize(bytecode::compile(prog)));
+}
 
-        if c == Some('l') { // Decrement mp
+// read_with_config function reads a diropql program code like read, on the given machine model
 
-            if mp == 0 {    // Wrap around
-                mp = 9999;
-            }
+pub fn read_with_config(prog: &str, config: &machine::MachineConfig) -> Result<String> {
+    let output = read_bytes_with_config(prog, config)?;
 
-            else {
-                mp = mp - 1;
-            }
-        }
+    return String::from_utf8(output).map_err(|_| Error::InvalidUtf8);
+}
 
-        else if c == Some('r') {    // Increment mp
+// read_bytes_with_config function reads a diropql program code like read_bytes, on the given machine model
 
-            if mp == 9999 { // Wrap around
-                mp = 0;
-            }
+pub fn read_bytes_with_config(prog: &str, config: &machine::MachineConfig) -> Result<Vec<u8>> {
+    let outcome = execute_with_config(prog, config, &bytecode::Limits::default());
 
-            else {
-                mp = mp + 1;
-            }
-        }
+    match outcome.stop {
+        bytecode::StopReason::Error(err) => return Err(err),
+        _ => return Ok(outcome.output),
+    }
+}
 
-        else if c == Some('i') {    // Increment content pointed by mp
+// read_strict function reads a diropql program code like read, but refuses a program with unmatched p or q
+// commands instead of running it
 
-            if memory_cells[mp] == 255 {    // Wrap around
-                memory_cells[mp] = 0;
-            }
+pub fn read_strict(prog: &str) -> Result<String> {
+    let output = read_bytes_strict(prog)?;
 
-            else {
-                memory_cells[mp] = memory_cells[mp] + 1;
-            }
-        }
+    return String::from_utf8(output).map_err(|_| Error::InvalidUtf8);
+}
 
-        else if c == Some('d') {    // Decrement content pointed by mp
+// read_bytes_strict function reads a diropql program code like read_bytes, but refuses a program with unmatched p
+// or q commands instead of running it
 
-            if memory_cells[mp] == 0 {  // Wrap around
-                memory_cells[mp] = 255;
-            }
+pub fn read_bytes_strict(prog: &str) -> Result<Vec<u8>> {
+    let diagnostics = validate::validate(prog);
 
-            else {
-                memory_cells[mp] = memory_cells[mp] - 1;
-            }
-        }
+    if !diagnostics.is_empty() {
+        return Err(Error::InvalidProgram(diagnostics));
+    }
 
-        else if c == Some('o') {    // Push content pointed by mp to output queue
-            oq.push(memory_cells[mp]);
-        }
+    return read_bytes(prog);
+}
 
-        else if c == Some('p') {  // Change ip to the index of the matching q command
+// execute function runs an untrusted diropql program under the limits and returns its outcome, including the
+// output printed before a limit stopped it
 
-            if memory_cells[mp] == 0 {
+pub fn execute(prog: &str, limits: &bytecode::Limits) -> bytecode::Outcome {
+    return bytecode::run_with_limits(&optimize::optimize(bytecode::compile(prog)), limits);
+}
 
-                for pq in &pq_index {
+// execute_with_config function runs an untrusted diropql program like execute, on the given machine model
 
-                    if pq.0 == ip {
-                        ip = pq.1;
-                    }
-                }
-            }
-        }
+pub fn execute_with_config(prog: &str, config: &machine::MachineConfig, limits: &bytecode::Limits) -> bytecode::Outcome {
+    let ops = optimize::optimize_with_config(bytecode::compile_with_config(prog, config), config);
 
-        else if c == Some('q') {    // Change ip to the index of the maching p command
+    return bytecode::run_with_config(&ops, config, limits);
+}
 
-            if memory_cells[mp] != 0 {
-                    
-                for pq in &pq_index {
+// Submodule bytecode
+pub mod bytecode;
 
-                    if pq.1 == ip {
-                        ip = pq.0;
-                    }
-                }
-            }
-        }
+// Submodule machine
+pub mod machine;
 
-        ip = ip + 1;    // Increment instruction pointer after each command
-    }
+// Submodule optimize
+pub mod optimize;
 
-    // Convert ASCII codes in the output queue to string
-    let mut output_string = String::new();
-
-    for ascii_code in oq {
-        let ascii_char = std::char::from_u32(ascii_code as u32).unwrap();
-        output_string.push(ascii_char);
-    }
-
-    return output_string;
-}
+// Submodule validate
+pub mod validate;
 
 // Submodule zip
 pub mod zip;
@@ -207,7 +175,7 @@ mod dpql_test {
 		
 		let string = String::from("");
 		let expected = String::from("");
-		let received = read(&string);
+		let received = read(&string).unwrap();
 		
 		assert_eq!(expected, received);		
 	}
@@ -219,7 +187,7 @@ mod dpql_test {
 		
 		let string = String::from("abcwxyz");
 		let expected = String::from("");
-		let received = read(&string);
+		let received = read(&string).unwrap();
 		
 		assert_eq!(expected, received);
 	}
@@ -238,7 +206,7 @@ mod dpql_test {
 		
 		// oq will have one item, 65 which converts to "A" in ASCII
 		let expected = String::from("A");
-		let received = read(&string);
+		let received = read(&string).unwrap();
 		
 		assert_eq!(expected, received);
 	}
@@ -263,7 +231,7 @@ mod dpql_test {
 		
 		// oq will have one item, 65, which converts to "A" in ASCII
 		let expected = String::from("A");
-		let received = read(&string);
+		let received = read(&string).unwrap();
 		
 		assert_eq!(expected, received);
 	}
@@ -288,7 +256,7 @@ mod dpql_test {
 
 		// oq will have one item, 65 which converts to "A" in ASCII
 		let expected = String::from("A");
-		let received = read(&string);
+		let received = read(&string).unwrap();
 		
 		assert_eq!(expected, received);	
 	}
@@ -311,11 +279,22 @@ mod dpql_test {
 		string.push_str("ro");
 		
 		let expected = String::from("A");
-		let received = read(&string);
+		let received = read(&string).unwrap();
 
 		assert_eq!(expected, received);
 	}
 	
+	#[test]
+	fn read_pt7_unbalanced() {
+		// A q command with a non-zero cell and no matching p, and a p command with a zero cell and no matching q
+		
+		let string = String::from("iq");
+		assert_eq!(Err(Error::UnbalancedLoop { pos: 1 }), read(&string));
+		
+		let string = String::from("rp");
+		assert_eq!(Err(Error::UnbalancedLoop { pos: 1 }), read(&string));
+	}
+	
 	// Write Read tests
 	
 	#[test]
@@ -324,7 +303,7 @@ mod dpql_test {
 		
 		let string = String::from("the quick brown fox jumps over the lazy dog. THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG.");
 		let encoded = write(&string);
-		let decoded = read(&encoded);
+		let decoded = read(&encoded).unwrap();
 		
 		assert_eq!(string, decoded);		// Check if decoded equals original.
 	}
@@ -335,7 +314,7 @@ mod dpql_test {
 		
 		let string = String::from(" !'\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~"); 
 		let encoded = write(&string);
-		let decoded = read(&encoded);
+		let decoded = read(&encoded).unwrap();
 		
 		assert_eq!(string, decoded);		// Check if decoded equals original			
 	}
@@ -347,9 +326,79 @@ mod dpql_test {
 	
 		let string = String::from("Carriage Return:\rSpace: Newline:\nTab:\t");
 		let encoded = write(&string);
-		let decoded = read(&encoded);
+		let decoded = read(&encoded).unwrap();
 		
 		assert_eq!(string, decoded);
 		print!("{}", string);
 	}
-}
\ No newline at end of file
+	
+	#[test]
+	fn write_read_pt4_bytes() {
+		// Every byte value from 0 to 255 must round-trip, including bytes that are not valid UTF-8
+		
+		let data: Vec<u8> = (0..=255).collect();
+		let encoded = write_bytes(&data);
+		let decoded = read_bytes(&encoded).unwrap();
+		
+		assert_eq!(data, decoded);
+	}
+	
+	#[test]
+	fn write_read_pt5_unicode() {
+		// Non-ASCII characters are written as their UTF-8 bytes instead of being truncated
+		
+		let string = String::from("Grüße, 世界! ✓");
+		let encoded = write(&string);
+		let decoded = read(&encoded).unwrap();
+		
+		assert_eq!(string, decoded);
+	}
+	
+	#[test]
+	fn read_pt8_invalid_utf8() {
+		// A lone 0xFF byte can be read as bytes but not as text
+		
+		let string = String::from("do");
+		
+		assert_eq!(vec![255], read_bytes(&string).unwrap());
+		assert_eq!(Err(Error::InvalidUtf8), read(&string));
+	}
+	
+	#[test]
+	fn read_pt9_strict() {
+		// Strict mode refuses every unmatched command, even one the program never takes
+		
+		// Increment the first memory cell 65 times
+		let mut string = String::from("");
+		for _i in 0..65 {
+			string.push('i');
+		}
+		
+		// A stray p before the output is never taken because the cell is not zero
+		let balanced = format!("{}o", string);
+		let unbalanced = format!("{}po", string);
+		
+		assert_eq!(Ok(String::from("A")), read(&unbalanced));
+		assert_eq!(Err(Error::InvalidProgram(validate::validate(&unbalanced))), read_strict(&unbalanced));
+		assert_eq!(Ok(String::from("A")), read_strict(&balanced));
+	}
+
+	#[test]
+	fn read_pt10_config() {
+		// The default machine model reads like read; other models change what a program prints
+		
+		// Increment the first memory cell 65 times, step the next one below zero and move off the start of the tape
+		let mut string = String::from("");
+		for _i in 0..65 {
+			string.push('i');
+		}
+		string.push_str("ordollo");
+		
+		let saturate = machine::MachineConfig { cell_overflow: machine::CellOverflow::Saturate, ..machine::MachineConfig::default() };
+		let bounded = machine::MachineConfig { pointer_overflow: machine::PointerOverflow::Error, ..machine::MachineConfig::default() };
+		
+		assert_eq!(read_bytes(&string), read_bytes_with_config(&string, &machine::MachineConfig::default()));
+		assert_eq!(Ok(vec![65, 0, 0]), read_bytes_with_config(&string, &saturate));
+		assert_eq!(Err(Error::PointerOutOfRange), read_with_config(&string, &bounded));
+	}
+}
@@ -0,0 +1,566 @@
+/* Bytecode of diropql programs: the source is parsed once into a compact instruction vector that read_bytes runs
+after the optimize pass.
+
+Runs of i and d commands fold into one Add and runs of l and r commands into one Move; other characters are
+skipped, so runs fold across them. Every p and q carries the index of the instruction after its partner, which
+makes the jump table O(1). A p or q without a partner keeps its position in the source and fails only if it is
+taken, like in the character interpreter.
+
+run_with_limits executes untrusted programs under an instruction budget, an output cap and a cancellation flag.
+A step is one bytecode instruction, so a folded run or a replaced loop counts once, except that every pointer
+move of a Scan is a step of its own.
+
+The *_with_config functions compile and run for another machine model (see the machine module). Runs fold only
+where the model cannot tell the difference: a run that changes direction is split where a cell or the pointer
+would stop at the end of its range instead of wrapping. */
+
+use super::machine::{CellOverflow, MachineConfig};
+use crate::error::{Error, Result};
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Number of memory cells of the diropql machine
+pub const TAPE_LEN: usize = 10000;
+
+// Number of steps between two reads of the cancellation flag
+const CANCEL_CHECK_INTERVAL: u64 = 1024;
+
+// One instruction of a compiled diropql program
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    // Add to the current cell, negative to subtract; a run of i and d commands
+    Add(i64),
+
+    // Move the memory pointer right, negative to move left; a run of l and r commands
+    Move(i64),
+
+    // Push the current cell to the output queue; an o command
+    Output,
+
+    // Jump to the instruction after the matching q if the current cell is zero; a p command
+    JumpIfZero(usize),
+
+    // Jump to the instruction after the matching p if the current cell is not zero; a q command
+    JumpIfNonZero(usize),
+
+    // A p command without a matching q at this character position of the source
+    UnmatchedOpen(usize),
+
+    // A q command without a matching p at this character position of the source
+    UnmatchedClose(usize),
+
+    // Set the current cell to zero; a clear loop such as pdq
+    Clear,
+
+    // Add the current cell times factor to the cell offset cells to the right, unless the current cell is zero;
+    // part of a multiply loop
+    MulAdd { offset: i64, factor: i64 },
+
+    // Move the memory pointer right by the step until it reaches a zero cell; a scan loop such as prq
+    Scan(i64),
+}
+
+// compile function parses a diropql program into bytecode for the default machine
+
+pub fn compile(prog: &str) -> Vec<Op> {
+    return compile_with_config(prog, &MachineConfig::default());
+}
+
+// compile_with_config function parses a diropql program into bytecode for the machine model
+
+pub fn compile_with_config(prog: &str, config: &MachineConfig) -> Vec<Op> {
+    let mut ops: Vec<Op> = Vec::new();
+
+    // Indices in ops of the p commands still waiting for their q
+    let mut open: Vec<usize> = Vec::new();
+
+    for (pos, c) in prog.chars().enumerate() {
+        match c {
+            'i' => add(&mut ops, 1, config),
+            'd' => add(&mut ops, -1, config),
+            'r' => shift(&mut ops, 1, config),
+            'l' => shift(&mut ops, -1, config),
+            'o' => ops.push(Op::Output),
+
+            'p' => {
+                // Patched into a jump once the matching q is found
+                open.push(ops.len());
+                ops.push(Op::UnmatchedOpen(pos));
+            }
+
+            'q' => match open.pop() {
+                Some(start) => {
+                    ops[start] = Op::JumpIfZero(ops.len() + 1);
+                    ops.push(Op::JumpIfNonZero(start + 1));
+                }
+
+                None => ops.push(Op::UnmatchedClose(pos)),
+            },
+
+            _ => {}
+        }
+    }
+
+    return ops;
+}
+
+// add function folds an increment into the Add at the end of the bytecode; an Add that cancels out is removed
+
+fn add(ops: &mut Vec<Op>, amount: i64, config: &MachineConfig) {
+    let wraps = config.cell_overflow == CellOverflow::Wrap;
+
+    match ops.last_mut() {
+        // Without wrapping, a change of direction can start from the end of the range
+        Some(Op::Add(total)) if wraps || total.signum() == amount.signum() => {
+            *total += amount;
+
+            if wraps && *total & i64::from(config.cell_width.max()) == 0 {
+                ops.pop();
+            }
+        }
+
+        _ => ops.push(Op::Add(amount)),
+    }
+}
+
+// shift function folds a pointer move into the Move at the end of the bytecode; a Move that cancels out is removed
+
+fn shift(ops: &mut Vec<Op>, amount: i64, config: &MachineConfig) {
+    match ops.last_mut() {
+        // A pointer that stops at the end of the tape makes a change of direction observable
+        Some(Op::Move(total)) if !config.pointer_errors() || total.signum() == amount.signum() => {
+            *total += amount;
+
+            // Growing the tape is not observable, so only a wrapping tape cancels a whole lap
+            let cancels = match config.wrap_len() {
+                Some(len) => total.rem_euclid(len as i64) == 0,
+                None => *total == 0,
+            };
+
+            if cancels {
+                ops.pop();
+            }
+        }
+
+        _ => ops.push(Op::Move(amount)),
+    }
+}
+
+// Limits on the execution of a program; None means unlimited
+
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    pub max_steps: Option<u64>,     // Number of steps the program may execute
+    pub max_output: Option<usize>,     // Number of bytes the program may output
+    pub cancel: Option<Arc<AtomicBool>>,     // Set from another thread to stop the program
+}
+
+// Reason a program stopped running
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    // The program ran to its end
+    Finished,
+
+    // The program used up its step budget
+    StepLimit,
+
+    // The program tried to output more bytes than the output cap
+    OutputLimit,
+
+    // The cancellation flag was set
+    Cancelled,
+
+    // The program took a p or q command without a partner
+    Error(Error),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Finished => write!(f, "finished"),
+            StopReason::StepLimit => write!(f, "step limit reached"),
+            StopReason::OutputLimit => write!(f, "output limit reached"),
+            StopReason::Cancelled => write!(f, "cancelled"),
+            StopReason::Error(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// Result of running a program under limits: the output so far, the steps executed and why it stopped
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub output: Vec<u8>,
+    pub steps: u64,
+    pub stop: StopReason,
+}
+
+// run function executes bytecode on a fresh tape without limits and returns the bytes in its output queue
+
+pub fn run(ops: &[Op]) -> Result<Vec<u8>> {
+    let outcome = run_with_limits(ops, &Limits::default());
+
+    match outcome.stop {
+        StopReason::Finished => return Ok(outcome.output),
+        StopReason::Error(err) => return Err(err),
+        reason => unreachable!("a run without limits stopped early: {}", reason),
+    }
+}
+
+// run_with_limits function executes bytecode on a fresh tape of the default machine until it ends, fails or trips
+// one of the limits
+
+pub fn run_with_limits(ops: &[Op], limits: &Limits) -> Outcome {
+    return run_with_config(ops, &MachineConfig::default(), limits);
+}
+
+// run_with_config function executes bytecode compiled for the machine model on a fresh tape until it ends, fails
+// or trips one of the limits
+
+pub fn run_with_config(ops: &[Op], config: &MachineConfig, limits: &Limits) -> Outcome {
+    let mut machine = match Machine::new(config) {
+        Ok(machine) => machine,
+        Err(err) => return Outcome { output: Vec::new(), steps: 0, stop: StopReason::Error(err) },
+    };
+
+    // Initialize program counter, step counter and output queue
+    let mut pc: usize = 0;
+    let mut steps: u64 = 0;
+    let mut oq: Vec<u8> = Vec::new();
+
+    let stop = 'run: loop {
+        let op = match ops.get(pc) {
+            Some(&op) => op,
+            None => break StopReason::Finished,
+        };
+
+        if let Some(reason) = limit_reached(limits, steps) {
+            break reason;
+        }
+
+        if op == Op::Output && limits.max_output.is_some_and(|max| oq.len() >= max) {
+            break StopReason::OutputLimit;
+        }
+
+        steps += 1;
+        pc += 1;
+
+        let result = match op {
+            Op::Add(amount) => machine.add(machine.mp, i128::from(amount)),
+            Op::Move(amount) => machine.shift(amount),
+
+            Op::Output => {
+                // A wider cell outputs its low 8 bits
+                oq.push(machine.cell() as u8);
+                Ok(())
+            }
+
+            Op::JumpIfZero(target) => {
+                if machine.cell() == 0 {
+                    pc = target;
+                }
+
+                Ok(())
+            }
+
+            Op::JumpIfNonZero(target) => {
+                if machine.cell() != 0 {
+                    pc = target;
+                }
+
+                Ok(())
+            }
+
+            // No matching q command to jump to
+            Op::UnmatchedOpen(pos) if machine.cell() == 0 => Err(Error::UnbalancedLoop { pos }),
+
+            // No matching p command to jump to
+            Op::UnmatchedClose(pos) if machine.cell() != 0 => Err(Error::UnbalancedLoop { pos }),
+
+            Op::UnmatchedOpen(_) | Op::UnmatchedClose(_) => Ok(()),
+
+            Op::Clear => {
+                let mp = machine.mp;
+                machine.cells[mp] = 0;
+                Ok(())
+            }
+
+            Op::MulAdd { offset, factor } => {
+                let value = machine.cell();
+
+                // The loop does not run on a zero cell, so it must not reach the target either
+                if value == 0 {
+                    Ok(())
+                }
+
+                else {
+                    machine.locate(offset).and_then(|target| machine.add(target, i128::from(value) * i128::from(factor)))
+                }
+            }
+
+            Op::Scan(step) => {
+                // A tape without a zero cell is scanned forever, so every move is a step
+                let mut result = Ok(());
+
+                while result.is_ok() && machine.cell() != 0 {
+                    if let Some(reason) = limit_reached(limits, steps) {
+                        break 'run reason;
+                    }
+
+                    result = machine.shift(step);
+                    steps += 1;
+                }
+
+                result
+            }
+        };
+
+        if let Err(err) = result {
+            break StopReason::Error(err);
+        }
+    };
+
+    return Outcome { output: oq, steps, stop };
+}
+
+// Tape and memory pointer of a running program
+
+struct Machine<'a> {
+    config: &'a MachineConfig,
+    cells: Vec<u32>,
+    mp: usize,
+}
+
+impl<'a> Machine<'a> {
+    // new function builds the tape of the machine model; an unbounded tape starts with one cell
+
+    fn new(config: &'a MachineConfig) -> Result<Self> {
+        if config.tape_len == Some(0) {
+            return Err(Error::EmptyTape);
+        }
+
+        return Ok(Machine { config, cells: vec![0; config.tape_len.unwrap_or(1)], mp: 0 });
+    }
+
+    // cell function returns the value of the current cell
+
+    fn cell(&self) -> u32 {
+        return self.cells[self.mp];
+    }
+
+    // add function adds delta to the cell at index under the cell overflow policy
+
+    fn add(&mut self, index: usize, delta: i128) -> Result<()> {
+        self.cells[index] = self.config.add(self.cells[index], delta).ok_or(Error::CellOverflow)?;
+
+        return Ok(());
+    }
+
+    // shift function moves the memory pointer under the pointer overflow policy
+
+    fn shift(&mut self, amount: i64) -> Result<()> {
+        self.mp = self.locate(amount)?;
+
+        return Ok(());
+    }
+
+    // locate function returns the index of the cell offset cells right of the memory pointer, growing the tape if
+    // the policy allows; growing at the left end moves every cell, so mp is updated
+
+    fn locate(&mut self, offset: i64) -> Result<usize> {
+        let target = self.mp as i64 + offset;
+
+        if let Some(len) = self.config.wrap_len() {
+            return Ok(target.rem_euclid(len as i64) as usize);
+        }
+
+        if (0..self.cells.len() as i64).contains(&target) {
+            return Ok(target as usize);
+        }
+
+        if self.config.pointer_errors() {
+            return Err(Error::PointerOutOfRange);
+        }
+
+        // The tape at least doubles, so a long walk grows it only a few times
+        if target < 0 {
+            let extra = (target.unsigned_abs() as usize).max(self.cells.len());
+            self.cells.splice(0..0, std::iter::repeat_n(0, extra));
+            self.mp += extra;
+
+            return Ok((target + extra as i64) as usize);
+        }
+
+        let len = (target as usize + 1).max(self.cells.len() * 2);
+        self.cells.resize(len, 0);
+
+        return Ok(target as usize);
+    }
+}
+
+// limit_reached function returns why the program must stop before its next step, if it must
+
+fn limit_reached(limits: &Limits, steps: u64) -> Option<StopReason> {
+    if limits.max_steps.is_some_and(|max| steps >= max) {
+        return Some(StopReason::StepLimit);
+    }
+
+    // Reading the flag on every step would slow down every program
+    if steps.is_multiple_of(CANCEL_CHECK_INTERVAL) && limits.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+        return Some(StopReason::Cancelled);
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod bytecode_test {
+    use super::*;
+    use crate::dpql::machine::{CellWidth, PointerOverflow};
+
+    #[test]
+    fn compile_pt1_folding() {
+        // Runs fold across other characters; runs that cancel out leave no instruction
+
+        assert_eq!(vec![Op::Add(3), Op::Move(1), Op::Output], compile("ii x i rrl o"));
+        assert_eq!(vec![Op::Add(-2), Op::Move(-2), Op::Output], compile("ddll o"));
+        assert_eq!(vec![Op::Output], compile("idrlo"));
+        assert_eq!(Vec::<Op>::new(), compile(&"i".repeat(256)));
+    }
+
+    #[test]
+    fn compile_pt2_jumps() {
+        // Every bracket points past its partner; a lone bracket keeps its source position
+
+        let expected = vec![
+            Op::Add(2),
+            Op::JumpIfZero(7),
+            Op::Move(1),
+            Op::Add(1),
+            Op::Move(-1),
+            Op::Add(-1),
+            Op::JumpIfNonZero(2),
+            Op::UnmatchedClose(8),
+            Op::UnmatchedOpen(9),
+        ];
+
+        assert_eq!(expected, compile("iiprildqqp"));
+    }
+
+    #[test]
+    fn run_pt1_programs() {
+        // Loops, wrapping and lone brackets behave like the character interpreter
+
+        assert_eq!(Ok(vec![65]), run(&compile(&format!("{}prildqro", "i".repeat(65)))));
+        assert_eq!(Ok(vec![255, 7]), run(&compile(&format!("dol{}o", "i".repeat(7)))));
+        assert_eq!(Ok(vec![]), run(&compile("qpiq")));
+        assert_eq!(Err(Error::UnbalancedLoop { pos: 1 }), run(&compile("iq")));
+        assert_eq!(Err(Error::UnbalancedLoop { pos: 1 }), run(&compile("rp")));
+    }
+
+    #[test]
+    fn run_with_limits_pt1_steps() {
+        // An endless loop runs out of steps; the outcome keeps the output printed so far
+
+        let outcome = run_with_limits(&compile("ioipq"), &Limits { max_steps: Some(1000), ..Limits::default() });
+        assert_eq!(Outcome { output: vec![1], steps: 1000, stop: StopReason::StepLimit }, outcome);
+
+        // A scan over a tape without a zero cell counts every move
+        let prog = format!("i{}prq", "ri".repeat(TAPE_LEN - 1));
+        let ops = crate::dpql::optimize::optimize(compile(&prog));
+        let outcome = run_with_limits(&ops, &Limits { max_steps: Some(50_000), ..Limits::default() });
+        assert_eq!((50_000, StopReason::StepLimit), (outcome.steps, outcome.stop));
+
+        let outcome = run_with_limits(&compile("iiooq"), &Limits { max_steps: Some(4), ..Limits::default() });
+        assert_eq!(Outcome { output: vec![2, 2], steps: 4, stop: StopReason::Error(Error::UnbalancedLoop { pos: 4 }) }, outcome);
+    }
+
+    #[test]
+    fn run_with_limits_pt2_output() {
+        // Output stops at the cap; a program that fits the cap finishes
+
+        let outcome = run_with_limits(&compile("ipoq"), &Limits { max_output: Some(5), ..Limits::default() });
+        assert_eq!(Outcome { output: vec![1; 5], steps: 12, stop: StopReason::OutputLimit }, outcome);
+
+        let outcome = run_with_limits(&compile("iooo"), &Limits { max_output: Some(3), ..Limits::default() });
+        assert_eq!(Outcome { output: vec![1; 3], steps: 4, stop: StopReason::Finished }, outcome);
+    }
+
+    #[test]
+    fn run_with_limits_pt3_cancel() {
+        // A flag set before the start or from another thread stops an endless loop
+
+        let cancel = Arc::new(AtomicBool::new(true));
+        let outcome = run_with_limits(&compile("ipq"), &Limits { cancel: Some(cancel.clone()), ..Limits::default() });
+        assert_eq!(Outcome { output: vec![], steps: 0, stop: StopReason::Cancelled }, outcome);
+
+        cancel.store(false, Ordering::Relaxed);
+        let limits = Limits { cancel: Some(cancel.clone()), ..Limits::default() };
+        let runner = std::thread::spawn(move || run_with_limits(&compile("ipq"), &limits));
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cancel.store(true, Ordering::Relaxed);
+
+        let outcome = runner.join().unwrap();
+        assert_eq!(StopReason::Cancelled, outcome.stop);
+        assert!(outcome.steps > 0);
+    }
+
+    #[test]
+    fn compile_pt3_config() {
+        // Runs that change direction are kept apart where the model could stop at an end of the range
+
+        let saturate = MachineConfig { cell_overflow: CellOverflow::Saturate, ..MachineConfig::default() };
+        let bounded = MachineConfig { pointer_overflow: PointerOverflow::Error, ..MachineConfig::default() };
+        let unbounded = MachineConfig { tape_len: None, ..MachineConfig::default() };
+
+        assert_eq!(vec![Op::Add(2), Op::Add(-1)], compile_with_config("iidlr", &saturate));
+        assert_eq!(vec![Op::Add(1), Op::Move(2), Op::Move(-1)], compile_with_config("iidrrl", &bounded));
+        assert_eq!(vec![Op::Move(-1)], compile_with_config("rlrll", &unbounded));
+        assert_eq!(Vec::<Op>::new(), compile(&"r".repeat(TAPE_LEN)));
+    }
+
+    #[test]
+    fn run_with_config_pt1_tape() {
+        // An unbounded tape grows to the left and right, a bounded one can refuse to, and an empty one cannot run
+
+        let run_on = |prog: &str, config: &MachineConfig| run_with_config(&compile_with_config(prog, config), config, &Limits::default());
+        let unbounded = MachineConfig { tape_len: None, ..MachineConfig::default() };
+        let bounded = MachineConfig { tape_len: Some(3), pointer_overflow: PointerOverflow::Error, ..MachineConfig::default() };
+        let grow = MachineConfig { tape_len: Some(2), pointer_overflow: PointerOverflow::Grow, ..MachineConfig::default() };
+
+        assert_eq!(vec![2, 1, 2, 0], run_on("liiorrioll o lllo", &unbounded).output);
+        assert_eq!(vec![2, 1, 2, 0], run_on("liiorrioll o lllo", &grow).output);
+        assert_eq!(vec![2, 1, 2, 2], run_on("liiorrioll o lllo", &MachineConfig { tape_len: Some(3), ..MachineConfig::default() }).output);
+
+        let outcome = run_on("irrorro", &bounded);
+        assert_eq!((vec![0], StopReason::Error(Error::PointerOutOfRange)), (outcome.output, outcome.stop));
+        assert_eq!(StopReason::Error(Error::PointerOutOfRange), run_on("iririprq", &bounded).stop);
+        assert_eq!(StopReason::Error(Error::EmptyTape), run_on("o", &MachineConfig { tape_len: Some(0), ..bounded }).stop);
+    }
+
+    #[test]
+    fn run_with_config_pt2_cells() {
+        // Wider cells output their low byte and only reach zero at their own width; other policies stop at the ends
+
+        let run_on = |prog: &str, config: &MachineConfig| run_with_config(&compile_with_config(prog, config), config, &Limits::default());
+        let wide = MachineConfig { cell_width: CellWidth::U16, ..MachineConfig::default() };
+        let widest = MachineConfig { cell_width: CellWidth::U32, ..MachineConfig::default() };
+        let saturate = MachineConfig { cell_overflow: CellOverflow::Saturate, ..MachineConfig::default() };
+        let error = MachineConfig { cell_overflow: CellOverflow::Error, ..MachineConfig::default() };
+
+        let prog = format!("{}proq", "i".repeat(256));
+        assert_eq!(Vec::<u8>::new(), run_on(&prog, &MachineConfig::default()).output);
+        assert_eq!(vec![0], run_on(&prog, &wide).output);
+        assert_eq!(vec![255, 0], run_on("doro", &widest).output);
+
+        assert_eq!(vec![0, 1, 255], run_on(&format!("doio{}o", "i".repeat(300)), &saturate).output);
+
+        let outcome = run_on("iodo do", &error);
+        assert_eq!((vec![1, 0], StopReason::Error(Error::CellOverflow)), (outcome.output, outcome.stop));
+    }
+}
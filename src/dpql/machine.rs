@@ -0,0 +1,131 @@
+/* Machine model of the diropql interpreter: tape length, cell width and what happens when the memory pointer
+leaves the tape or a cell leaves its range.
+
+The default is the original machine: 10,000 cells of 8 bits, a pointer that wraps at both ends of the tape and
+cells that wrap at 255. An o command outputs the low 8 bits of a wider cell. */
+
+use super::bytecode::TAPE_LEN;
+
+// Number of bits of every memory cell
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CellWidth {
+    #[default]
+    U8,
+    U16,
+    U32,
+}
+
+impl CellWidth {
+    // max function returns the largest value a cell can hold
+
+    pub fn max(self) -> u32 {
+        match self {
+            CellWidth::U8 => return u32::from(u8::MAX),
+            CellWidth::U16 => return u32::from(u16::MAX),
+            CellWidth::U32 => return u32::MAX,
+        }
+    }
+}
+
+// What an l or r command does at an end of a bounded tape
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PointerOverflow {
+    // Continue at the other end of the tape
+    #[default]
+    Wrap,
+
+    // Stop the program with Error::PointerOutOfRange
+    Error,
+
+    // Add zero cells at that end of the tape
+    Grow,
+}
+
+// What an i or d command does to a cell at the end of its range
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CellOverflow {
+    // Continue at the other end of the range
+    #[default]
+    Wrap,
+
+    // Stay at 0 or at the largest value
+    Saturate,
+
+    // Stop the program with Error::CellOverflow
+    Error,
+}
+
+// Machine model a diropql program runs on
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MachineConfig {
+    pub tape_len: Option<usize>,     // Number of cells; None is an unbounded tape that grows in both directions
+    pub cell_width: CellWidth,
+    pub pointer_overflow: PointerOverflow,     // Ignored by an unbounded tape
+    pub cell_overflow: CellOverflow,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        return MachineConfig {
+            tape_len: Some(TAPE_LEN),
+            cell_width: CellWidth::default(),
+            pointer_overflow: PointerOverflow::default(),
+            cell_overflow: CellOverflow::default(),
+        };
+    }
+}
+
+impl MachineConfig {
+    // wrap_len function returns the tape length if the memory pointer wraps around the tape
+
+    pub(super) fn wrap_len(&self) -> Option<usize> {
+        match (self.tape_len, self.pointer_overflow) {
+            (Some(len), PointerOverflow::Wrap) => return Some(len),
+            _ => return None,
+        }
+    }
+
+    // pointer_errors function returns whether moving the memory pointer off the tape is an error
+
+    pub(super) fn pointer_errors(&self) -> bool {
+        return self.tape_len.is_some() && self.pointer_overflow == PointerOverflow::Error;
+    }
+
+    // add function adds delta to a cell value under the cell width and overflow policy; None is an overflow error
+
+    pub(super) fn add(&self, value: u32, delta: i128) -> Option<u32> {
+        let max = i128::from(self.cell_width.max());
+        let sum = i128::from(value) + delta;
+
+        match self.cell_overflow {
+            // The range is a power of two, so masking the two's complement sum wraps it
+            CellOverflow::Wrap => return Some((sum & max) as u32),
+            CellOverflow::Saturate => return Some(sum.clamp(0, max) as u32),
+            CellOverflow::Error => return if (0..=max).contains(&sum) { Some(sum as u32) } else { None },
+        }
+    }
+}
+
+#[cfg(test)]
+mod machine_test {
+    use super::*;
+
+    #[test]
+    fn add_pt1_policies() {
+        // Every policy agrees inside the range and differs at its ends
+
+        let wrap = MachineConfig::default();
+        let saturate = MachineConfig { cell_overflow: CellOverflow::Saturate, ..wrap };
+        let error = MachineConfig { cell_overflow: CellOverflow::Error, cell_width: CellWidth::U16, ..wrap };
+
+        assert_eq!((Some(7), Some(7), Some(7)), (wrap.add(5, 2), saturate.add(5, 2), error.add(5, 2)));
+        assert_eq!((Some(1), Some(255)), (wrap.add(255, 2), saturate.add(255, 2)));
+        assert_eq!((Some(254), Some(0)), (wrap.add(0, -2), saturate.add(0, -2)));
+        assert_eq!((Some(256), None, None), (error.add(255, 1), error.add(65535, 1), error.add(0, -1)));
+        assert_eq!(Some(0), MachineConfig { cell_width: CellWidth::U32, ..wrap }.add(u32::MAX, 1));
+    }
+}
@@ -0,0 +1,344 @@
+/* Optimizing pass over diropql bytecode: loops whose body only adds and moves are replaced by single operations.
+
+A loop that returns to its starting cell and changes that cell by an odd amount per pass ends after a number of
+passes fixed by the starting value, so it becomes one MulAdd per other cell it changes followed by a Clear; pdq is
+the plain clear loop and pdriilq adds twice the cell to its right neighbour. A loop of a single Move is a scan
+loop such as prq and becomes a Scan. Loops that output, nest or may never end are kept as they are.
+
+On other machine models the step must divide the range of a wrapping cell, and a cell that saturates or errors
+must count down by exactly one with every other cell changed once per pass, so that it moves in one direction
+only. A multiply loop that moves the pointer is kept when the pointer errors at the ends of the tape, since the
+loop could stop part way. */
+
+use super::bytecode::Op;
+use super::machine::{CellOverflow, MachineConfig};
+
+// optimize function replaces the clear, multiply and scan loops of bytecode for the default machine and fixes up
+// the jump targets
+
+pub fn optimize(ops: Vec<Op>) -> Vec<Op> {
+    return optimize_with_config(ops, &MachineConfig::default());
+}
+
+// optimize_with_config function replaces the clear, multiply and scan loops of bytecode compiled for the machine
+// model and fixes up the jump targets
+
+pub fn optimize_with_config(ops: Vec<Op>, config: &MachineConfig) -> Vec<Op> {
+    let mut optimized: Vec<Op> = Vec::new();
+
+    // Indices in optimized of the loop starts still waiting for their end
+    let mut open: Vec<usize> = Vec::new();
+
+    for op in ops {
+        match op {
+            Op::JumpIfZero(_) => {
+                open.push(optimized.len());
+                optimized.push(op);
+            }
+
+            Op::JumpIfNonZero(_) => {
+                let start = open.pop().expect("compile pairs every loop end with a loop start");
+
+                match replace_loop(&optimized[start + 1..], config) {
+                    Some(replacement) => {
+                        optimized.truncate(start);
+                        optimized.extend(replacement);
+                    }
+
+                    None => {
+                        optimized[start] = Op::JumpIfZero(optimized.len() + 1);
+                        optimized.push(Op::JumpIfNonZero(start + 1));
+                    }
+                }
+            }
+
+            _ => optimized.push(op),
+        }
+    }
+
+    return optimized;
+}
+
+// replace_loop function returns the single operations that have the same effect as a loop with the given body,
+// or None if the loop is not a clear, multiply or scan loop
+
+fn replace_loop(body: &[Op], config: &MachineConfig) -> Option<Vec<Op>> {
+    if let [Op::Move(step)] = body {
+        return Some(vec![Op::Scan(*step)]);
+    }
+
+    let wraps = config.cell_overflow == CellOverflow::Wrap;
+
+    // Offsets that differ by whole laps of a wrapping tape are the same cell
+    let normalize = |offset: i64| match config.wrap_len() {
+        Some(len) => offset.rem_euclid(len as i64),
+        None => offset,
+    };
+
+    // Total change of every cell the body touches, by offset from the loop's cell
+    let mut offset: i64 = 0;
+    let mut changes: Vec<(i64, i64)> = Vec::new();
+
+    for &op in body {
+        match op {
+            Op::Move(_) if config.pointer_errors() => return None,
+            Op::Move(amount) => offset += amount,
+
+            Op::Add(amount) => match changes.iter_mut().find(|(target, _)| normalize(*target) == normalize(offset)) {
+                Some((_, total)) if wraps => *total += amount,
+                Some(_) => return None,
+                None => changes.push((offset, amount)),
+            },
+
+            _ => return None,
+        }
+    }
+
+    if normalize(offset) != 0 {
+        return None;
+    }
+
+    let step = changes.iter().find(|(target, _)| normalize(*target) == 0).map_or(0, |&(_, total)| total);
+    let max = i64::from(config.cell_width.max());
+
+    // The loop runs -cell / step times, so every other cell gains cell * total * (-1 / step)
+    let scale = if wraps {
+        // An even step can skip zero and loop forever
+        if step % 2 == 0 {
+            return None;
+        }
+
+        inverse(step).wrapping_neg()
+    }
+
+    else {
+        // Any other step passes zero or stops at an end of the range
+        if step != -1 {
+            return None;
+        }
+
+        1
+    };
+
+    let mut replacement: Vec<Op> = changes.iter()
+        .filter(|&&(target, total)| normalize(target) != 0 && (!wraps || total & max != 0))
+        .map(|&(target, total)| {
+            let factor = if wraps { total.wrapping_mul(scale) & max } else { total * scale };
+            Op::MulAdd { offset: target, factor }
+        })
+        .collect();
+
+    replacement.push(Op::Clear);
+
+    return Some(replacement);
+}
+
+// inverse function returns the multiplicative inverse of an odd number modulo 2^64; Newton's iteration doubles the
+// number of correct low bits each round, and every odd number is its own inverse modulo 8
+
+fn inverse(value: i64) -> i64 {
+    let mut x = value;
+
+    for _ in 0..5 {
+        x = x.wrapping_mul(2i64.wrapping_sub(value.wrapping_mul(x)));
+    }
+
+    return x;
+}
+
+#[cfg(test)]
+mod optimize_test {
+    use super::*;
+    use crate::dpql::bytecode::{compile, compile_with_config, run, run_with_config, Limits, StopReason};
+    use crate::dpql::machine::{CellWidth, PointerOverflow};
+
+    // plain function runs a program without the optimizing pass
+
+    fn plain(prog: &str) -> crate::error::Result<Vec<u8>> {
+        return run(&compile(prog));
+    }
+
+    // optimized function runs a program with the optimizing pass
+
+    fn optimized(prog: &str) -> crate::error::Result<Vec<u8>> {
+        return run(&optimize(compile(prog)));
+    }
+
+    // plain_with function runs a program on the machine model without the optimizing pass, keeping the output
+    // printed before an error
+
+    fn plain_with(prog: &str, config: &MachineConfig) -> (Vec<u8>, StopReason) {
+        let outcome = run_with_config(&compile_with_config(prog, config), config, &Limits::default());
+
+        return (outcome.output, outcome.stop);
+    }
+
+    // optimized_with function runs a program on the machine model with the optimizing pass, keeping the output
+    // printed before an error
+
+    fn optimized_with(prog: &str, config: &MachineConfig) -> (Vec<u8>, StopReason) {
+        let ops = optimize_with_config(compile_with_config(prog, config), config);
+        let outcome = run_with_config(&ops, config, &Limits::default());
+
+        return (outcome.output, outcome.stop);
+    }
+
+    #[test]
+    fn optimize_pt1_idioms() {
+        // Clear, multiply and scan loops become single operations
+
+        assert_eq!(vec![Op::Add(5), Op::Clear, Op::Output], optimize(compile("iiiiipdqo")));
+        assert_eq!(vec![Op::MulAdd { offset: 1, factor: 2 }, Op::Clear], optimize(compile("pdriilq")));
+        assert_eq!(vec![Op::Scan(1)], optimize(compile("prq")));
+        assert_eq!(vec![Op::Scan(-2)], optimize(compile("pllq")));
+
+        // Counting up and changing several cells, including one left of the tape start
+        let expected = vec![Op::MulAdd { offset: 2, factor: 253 }, Op::MulAdd { offset: -1, factor: 1 }, Op::Clear];
+        assert_eq!(expected, optimize(compile("pirriiillldrq")));
+    }
+
+    #[test]
+    fn optimize_pt2_kept() {
+        // Loops that output, nest, drift or step by an even amount stay loops with fixed-up targets
+
+        let expected = vec![Op::Add(2), Op::JumpIfZero(4), Op::Add(-2), Op::JumpIfNonZero(2), Op::Output];
+        assert_eq!(expected, optimize(compile("iipddqo")));
+
+        let expected = vec![Op::JumpIfZero(6), Op::Add(-1), Op::Move(1), Op::Clear, Op::Move(-1), Op::JumpIfNonZero(1)];
+        assert_eq!(expected, optimize(compile("pdrpdqlq")));
+
+        assert_eq!(compile("pdoq"), optimize(compile("pdoq")));
+        assert_eq!(compile("pdrq"), optimize(compile("pdrq")));
+        assert_eq!(compile("pq"), optimize(compile("pq")));
+        assert_eq!(vec![Op::UnmatchedOpen(0), Op::Clear], optimize(compile("ppdq")));
+    }
+
+    #[test]
+    fn optimize_pt3_differential() {
+        // Hand-written programs around the edge cases give the same output or error with and without the pass
+
+        let programs = [
+            "",
+            "iiiiipdqo",
+            "iiiiipdriilqrorolo",
+            "dpdriiiilqro",
+            "iiipirriiillldrqorrolllo",
+            "iiiiiiiiiipdlliirrqllollo",
+            "iiiiiipddqo",
+            "iiiiiiipdddqo",
+            "irirrirrpdqlllprqo",
+            "irirrirrpdqllllplqo",
+            "iiiiprrrpdqiiillldqrrro",
+            "iiprildpdrrirrillllqqrorroo",
+            "iq",
+            "rp",
+            "iipdq q pdq",
+            "ppdqiiioq",
+            "iiiipdroiilq",
+        ];
+
+        for prog in programs {
+            assert_eq!(plain(prog), optimized(prog), "program {:?}", prog);
+        }
+    }
+
+    #[test]
+    fn optimize_pt4_differential_random() {
+        // Random programs built from straight-line code and terminating loops agree with and without the pass on
+        // several machine models; loops count up only where the cell wraps within 256 passes, and 32-bit cells
+        // could count down for billions of passes
+
+        let models = [
+            (MachineConfig::default(), true),
+            (MachineConfig { cell_width: CellWidth::U16, pointer_overflow: PointerOverflow::Grow, ..MachineConfig::default() }, false),
+            (MachineConfig { tape_len: None, cell_overflow: CellOverflow::Saturate, ..MachineConfig::default() }, false),
+            (MachineConfig { tape_len: Some(16), pointer_overflow: PointerOverflow::Error, cell_overflow: CellOverflow::Error, ..MachineConfig::default() }, false),
+            (MachineConfig { tape_len: Some(7), cell_overflow: CellOverflow::Saturate, ..MachineConfig::default() }, false),
+        ];
+
+        let mut state: u32 = 0x9e37_79b9;
+        let mut next = move |bound: u32| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state % bound
+        };
+
+        for (config, counts_up) in models {
+            for _ in 0..300 {
+                let mut prog = String::new();
+
+                for _ in 0..1 + next(12) {
+                    match next(7) {
+                        // Straight-line code
+                        0 | 1 => {
+                            for _ in 0..1 + next(20) {
+                                prog.push(b"idlro"[next(5) as usize] as char);
+                            }
+                        }
+
+                        // Clear loop with an odd step
+                        2 => {
+                            let step = if next(2) == 0 || !counts_up { 'd' } else { 'i' };
+
+                            prog.push('p');
+                            prog.extend(std::iter::repeat_n(step, 1 + 2 * next(3) as usize));
+                            prog.push('q');
+                        }
+
+                        // Multiply loop into up to three cells on either side
+                        3 => {
+                            prog.push_str(if next(2) == 0 || !counts_up { "pd" } else { "pi" });
+
+                            for _ in 0..1 + next(3) {
+                                let (there, back) = if next(2) == 0 { ('r', 'l') } else { ('l', 'r') };
+                                let distance = 1 + next(3) as usize;
+
+                                prog.extend(std::iter::repeat_n(there, distance));
+                                prog.extend(std::iter::repeat_n(if next(2) == 0 { 'i' } else { 'd' }, 1 + next(4) as usize));
+                                prog.extend(std::iter::repeat_n(back, distance));
+                            }
+
+                            prog.push('q');
+                        }
+
+                        // Scan loop; the tape is mostly zero so it stops quickly
+                        4 => prog.push_str(["prq", "plq", "prrq", "plllq"][next(4) as usize]),
+
+                        // Loop that outputs and nests, counting the cell down by one
+                        5 => prog.push_str(["pdoq", "pdrpdqlq", "pdriolq", "pdrrioollq"][next(4) as usize]),
+
+                        // Stray q; a stray p could pair with it around a loop that never ends
+                        _ => prog.push('q'),
+                    }
+                }
+
+                if next(4) == 0 {
+                    prog.push('p');
+                }
+
+                assert_eq!(plain_with(&prog, &config), optimized_with(&prog, &config), "program {:?} on {:?}", prog, config);
+            }
+        }
+    }
+
+    #[test]
+    fn optimize_pt5_config() {
+        // Cells that do not wrap only replace loops counting down by one; a pointer that errors keeps moving loops
+
+        let saturate = MachineConfig { cell_overflow: CellOverflow::Saturate, ..MachineConfig::default() };
+        let bounded = MachineConfig { pointer_overflow: PointerOverflow::Error, ..MachineConfig::default() };
+        let wide = MachineConfig { cell_width: CellWidth::U16, ..MachineConfig::default() };
+
+        let expected = vec![Op::MulAdd { offset: 1, factor: 2 }, Op::Clear];
+        assert_eq!(expected, optimize_with_config(compile_with_config("pdriilq", &saturate), &saturate));
+        assert_eq!(compile_with_config("piriilq", &saturate), optimize_with_config(compile_with_config("piriilq", &saturate), &saturate));
+        assert_eq!(compile_with_config("pdridilq", &saturate), optimize_with_config(compile_with_config("pdridilq", &saturate), &saturate));
+        assert_eq!(compile_with_config("pdriilq", &bounded), optimize_with_config(compile_with_config("pdriilq", &bounded), &bounded));
+        assert_eq!(vec![Op::Clear, Op::Scan(1)], optimize_with_config(compile_with_config("pdqprq", &bounded), &bounded));
+
+        // 3 is inverted modulo 65536 rather than 256
+        let expected = vec![Op::MulAdd { offset: 1, factor: 43691 }, Op::Clear];
+        assert_eq!(expected, optimize_with_config(compile_with_config("pdddrilq", &wide), &wide));
+    }
+}
@@ -0,0 +1,85 @@
+/* Validation pass of diropql programs: finds every p and q command without a partner before the program runs.
+
+The commands are paired like compile pairs them: a q closes the latest open p, a q with no open p is unmatched,
+and every p still open at the end is unmatched. Lines and columns start at 1 and count characters. */
+
+use std::fmt;
+
+// An unmatched p or q command and where it is in the source
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub command: char,
+    pub line: usize,
+    pub column: usize,
+    pub pos: usize,     // Character index in the source, as in Error::UnbalancedLoop
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: unmatched '{}'", self.line, self.column, self.command)
+    }
+}
+
+// validate function returns a diagnostic for every unmatched p and q command, in source order
+
+pub fn validate(prog: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    // p commands still waiting for their q
+    let mut open: Vec<Diagnostic> = Vec::new();
+
+    let mut line: usize = 1;
+    let mut column: usize = 0;
+
+    for (pos, command) in prog.chars().enumerate() {
+        if command == '\n' {
+            line += 1;
+            column = 0;
+            continue;
+        }
+
+        column += 1;
+
+        match command {
+            'p' => open.push(Diagnostic { command, line, column, pos }),
+
+            // The guard closes the latest open p; a q with none open is unmatched
+            'q' if open.pop().is_none() => diagnostics.push(Diagnostic { command, line, column, pos }),
+
+            _ => {}
+        }
+    }
+
+    diagnostics.extend(open);
+    diagnostics.sort_by_key(|diagnostic| diagnostic.pos);
+
+    return diagnostics;
+}
+
+#[cfg(test)]
+mod validate_test {
+    use super::*;
+
+    #[test]
+    fn validate_pt1_balanced() {
+        // Nested and repeated loops, and programs without loops, have no diagnostics
+
+        assert_eq!(Vec::<Diagnostic>::new(), validate("iipriipdqlq\npdq"));
+        assert_eq!(Vec::<Diagnostic>::new(), validate(""));
+    }
+
+    #[test]
+    fn validate_pt2_unmatched() {
+        // Every stray command is reported with its line and column, in source order
+
+        let expected = vec![
+            Diagnostic { command: 'q', line: 1, column: 2, pos: 1 },
+            Diagnostic { command: 'q', line: 2, column: 4, pos: 6 },
+            Diagnostic { command: 'p', line: 3, column: 2, pos: 9 },
+        ];
+
+        assert_eq!(expected, validate("iq\npdqq\nipdpdq\n"));
+        assert_eq!("line 3, column 2: unmatched 'p'", expected[2].to_string());
+    }
+}
@@ -4,250 +4,892 @@ use crate::dpql::*;
 #[allow(unused_imports)]
 use crate::compressor::*;
 
+use crate::error::{Error, Result};
+
+use crate::compressor::bitio::{BitReader, BitWriter};
+
 use base85::{decode, encode};
 
-// write function takes in a message and converts it to a diropqlz file
+// Size in bytes of the fixed diropqlz header that precedes the compressed program
+const HEADER_LEN: usize = 33;
 
-pub fn write(text: &String) -> String {
-    // Convert the message to a diropql file
-    let dpql = crate::dpql::write(text);
+// Number of codebook entries the fixed header holds
+const LEGACY_CODEBOOK_LEN: usize = 10;
 
-    // Compress diropql file using bwt, mtf, rle, and huffman encode functions
-    let (bwt_encoded, bwt_index) = crate::compressor::bwt::encode(&dpql);
-	let bwt_index_u64: u64 = bwt_index.try_into().unwrap();
+// Size in bytes of the mlen, moffset and bwt_idx fields that start every header
+const FIELDS_LEN: usize = 17;
 
-    let mtf_encoded = crate::compressor::mtf::encode(&bwt_encoded, &String::from("\0dilopqr"));
+// Character placed after the magic string when a format version follows; it is never part of Base85 text
+const FORMAT_MARKER: char = '.';
 
-    let rle_encoded = crate::compressor::rle::encode(&mtf_encoded);
+// Format version of a file without the format marker: one block with the fixed header
+const LEGACY_VERSION: u8 = 0;
+
+// Format version written by this build: the version and flags bytes are followed by the headers of the flags
+const FORMAT_VERSION: u8 = 1;
+
+// Feature flag: the file is split into blocks (see the block module)
+pub(super) const FLAG_BLOCKS: u8 = 0x01;
+
+// Feature flag: the header ends with a self-describing codebook instead of nibble-packed code lengths
+const FLAG_CODEBOOK: u8 = 0x02;
+
+// Feature flag: every header ends with the CRC32 checksums of the original message and of the payload
+const FLAG_CHECKSUM: u8 = 0x04;
+
+// Feature flag: the file starts with copies of a format prefix holding a parity count and the rest is Reed–Solomon
+// encoded (see the fec module)
+const FLAG_FEC: u8 = 0x08;
+
+// Copies of the format prefix (version, flags, parity and a zero byte) that start a Reed–Solomon encoded file; each
+// fills one Base85 group, so one mangled character spoils at most one copy
+const FORMAT_COPIES: usize = 3;
+
+// Size in bytes of one copy of the format prefix
+const FORMAT_COPY_LEN: usize = 4;
+
+// Feature flag: the payload is encrypted with a passphrase and the header ends with its parameters (see the crypt module)
+const FLAG_ENCRYPTED: u8 = 0x10;
 
-    let (huffman_encoded, huffman_bitlens) = crate::compressor::huffman::encode(&rle_encoded);
+// Feature flag: the payload is encrypted to public keys and the header ends with the recipient stanzas (see the keys module)
+const FLAG_RECIPIENTS: u8 = 0x20;
 
-    // Determine the length of the message and the offset
-	#[allow(unused_assignments)]
-	let mut msg_len: u64 = 0;
+// Feature flag: the header ends with the sender's public key and signature (see the keys module)
+const FLAG_SIGNED: u8 = 0x40;
 
-	#[allow(unused_assignments)]
-	let mut msg_offset: u8 = 0;
+// Feature flag: the file is one Shamir share of another diropqlz file (see the shamir module)
+const FLAG_SHARE: u8 = 0x80;
 
-    if huffman_encoded.len() % 8 == 0 {
-        msg_len = huffman_encoded.len().try_into().unwrap();
-        msg_offset = 0;
+// Flags a share may combine with FLAG_SHARE; a share has no header of its own
+const SHARE_FLAGS: u8 = FLAG_SHARE | FLAG_FEC;
+
+// Longest codeword a nibble of the compact header can hold
+const NIBBLE_MAX_LEN: u8 = 15;
+
+// Longest BWT string a payload may expand to, which bounds the memory of the RLE stage; longer messages are
+// written in block mode
+pub const MAX_PROGRAM_LEN: usize = 1 << 24;
+
+// Base85 alphabet (RFC 1924) used by the base85 crate
+const BASE85_ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+// write function takes in a text message and converts its UTF-8 bytes to a diropqlz file
+
+pub fn write(text: &str) -> String {
+    return write_bytes(text.as_bytes());
+}
+
+// write_bytes function takes in arbitrary binary data and converts it to a diropqlz file; a message whose
+// program is longer than MAX_PROGRAM_LEN is written in block mode
+
+pub fn write_bytes(data: &[u8]) -> String {
+    match write_bytes_with_parity(data, 0) {
+        Ok(dpqlz) => return dpqlz,
+        Err(Error::ProgramTooLong { .. }) => return block::write_bytes(data, &block::BlockOptions::default()),
+        Err(err) => panic!("a parity of 0 writes no check bytes: {}", err),
     }
+}
 
-    else {
-        msg_len = (huffman_encoded.len() / 8 + 1).try_into().unwrap();
-        msg_offset = (8 - (huffman_encoded.len() % 8)).try_into().unwrap();
+// write_bytes_with_parity function converts binary data to a diropqlz file protected by `parity` Reed–Solomon
+// check bytes per 255-byte codeword; a parity of 0 writes no check bytes and 255 is rejected, as is a message
+// whose program is longer than MAX_PROGRAM_LEN
+
+pub fn write_bytes_with_parity(data: &[u8], parity: u8) -> Result<String> {
+    check_parity(parity)?;
+
+    let (mut meta_data, huffman_encoded) = compress(data)?;
+    meta_data.fec_parity = parity;
+
+    // Use the write_meta function to convert the compressed diropql file to a diropqlz file
+    let dpqlz = write_meta(&meta_data, &huffman_encoded);
+
+    return Ok(dpqlz);
+}
+
+// check_parity function rejects a Reed–Solomon parity count that leaves no data bytes in a codeword
+
+pub(super) fn check_parity(parity: u8) -> Result<()> {
+    if parity > fec::MAX_PARITY {
+        return Err(Error::InvalidRedundancy(parity));
+    }
+
+    return Ok(());
+}
+
+// write_encrypted function converts a text message to a diropqlz file whose payload only opens with the passphrase
+
+pub fn write_encrypted(text: &str, passphrase: &str) -> Result<String> {
+    return write_bytes_encrypted(text.as_bytes(), passphrase);
+}
+
+// write_bytes_encrypted function converts binary data to a diropqlz file whose payload only opens with the
+// passphrase; block mode has no encryption, so a message whose program is longer than MAX_PROGRAM_LEN is rejected
+
+pub fn write_bytes_encrypted(data: &[u8], passphrase: &str) -> Result<String> {
+    return write_bytes_encrypted_with_parity(data, passphrase, 0);
+}
+
+// write_bytes_encrypted_with_parity function encrypts like write_bytes_encrypted and adds `parity` Reed–Solomon
+// check bytes per 255-byte codeword like write_bytes_with_parity
+
+pub fn write_bytes_encrypted_with_parity(data: &[u8], passphrase: &str, parity: u8) -> Result<String> {
+    return write_sealed(data, passphrase, crypt::Encryption::new(), parity);
+}
+
+// write_sealed function compresses the data and encrypts the payload with the given key derivation parameters
+
+fn write_sealed(data: &[u8], passphrase: &str, encryption: crypt::Encryption, parity: u8) -> Result<String> {
+    check_parity(parity)?;
+
+    let (mut meta_data, huffman_encoded) = compress(data)?;
+    meta_data.fec_parity = parity;
+
+    meta_data.encryption = Some(encryption);
+    prepare_sealed(&mut meta_data, huffman_encoded.len());
+
+    let sealed = crypt::seal(&encryption, passphrase, &associated_data(&meta_data), &huffman_encoded)
+        .expect("argon2 accepts the costs of freshly created encryption parameters");
+
+    return Ok(write_meta(&meta_data, &sealed));
+}
+
+// prepare_sealed function fits the metadata to a payload of payload_len bytes that is about to be sealed with an AEAD
+
+fn prepare_sealed(meta_data: &mut DpqlzMeta, payload_len: usize) {
+    // The AEAD tag replaces the checksums, which would otherwise reveal a fingerprint of the message
+    meta_data.checksum = None;
+    meta_data.mlen = (payload_len + crypt::TAG_LEN) as u64;
+}
+
+// read function takes in a diropqlz file and returns the original text message
+
+pub fn read(prog: &str) -> Result<String> {
+    let data = read_bytes(prog)?;
+
+    return String::from_utf8(data).map_err(|_| Error::InvalidUtf8);
+}
+
+// read_bytes function takes in a diropqlz file and returns the original binary data
+
+pub fn read_bytes(prog: &str) -> Result<Vec<u8>> {
+    let (format, decoded_msg) = read_container(prog)?;
+
+    // Block mode files are decoded block by block
+    if format.flags & FLAG_BLOCKS != 0 {
+        return block::expand_blocks(&block::split_blocks(format, &decoded_msg)?, &block::BlockOptions::default());
+    }
+
+    // Split the diropqlz file into the metadata and the compressed diropql file
+    let (meta_data, huffman_encoded) = split_meta(format, &decoded_msg)?;
+
+    if meta_data.encryption.is_some() || meta_data.recipients.is_some() {
+        return Err(Error::EncryptionMismatch { encrypted: true });
+    }
+
+    return expand(&meta_data, &huffman_encoded);
+}
+
+// read_encrypted function takes in an encrypted diropqlz file and its passphrase and returns the original text message
+
+pub fn read_encrypted(prog: &str, passphrase: &str) -> Result<String> {
+    let data = read_bytes_encrypted(prog, passphrase)?;
+
+    return String::from_utf8(data).map_err(|_| Error::InvalidUtf8);
+}
+
+// read_bytes_encrypted function takes in an encrypted diropqlz file and its passphrase and returns the original
+// binary data; files without encryption are rejected so that a stripped encryption cannot go unnoticed
+
+pub fn read_bytes_encrypted(prog: &str, passphrase: &str) -> Result<Vec<u8>> {
+    let (meta_data, sealed) = read_meta(prog)?;
+
+    let encryption = match meta_data.encryption {
+        Some(encryption) => encryption,
+        None => return Err(Error::EncryptionMismatch { encrypted: false }),
+    };
+
+    let huffman_encoded = crypt::open(&encryption, passphrase, &associated_data(&meta_data), &sealed)?;
+
+    return expand(&meta_data, &huffman_encoded);
+}
+
+// compress function converts binary data to a diropql program, compresses it with bwt, mtf, rle and huffman and
+// returns the metadata and the packed payload; the reader refuses programs longer than MAX_PROGRAM_LEN, so they
+// are rejected here too
+
+fn compress(data: &[u8]) -> Result<(DpqlzMeta, Vec<u8>)> {
+    // Convert the message to a diropql file
+    let dpql = crate::dpql::write_bytes(data);
+
+    // The BWT string holds the program and the sentinel
+    let program_len = dpql.len() + 1;
+    if program_len > MAX_PROGRAM_LEN {
+        return Err(Error::ProgramTooLong { len: program_len as u64 });
     }
 
+    // Compress diropql file using bwt, mtf, rle, and huffman encode functions
+    let (mut bwt_encoded, bwt_index) = crate::compressor::bwt::encode(dpql.as_bytes());
+    let bwt_index_u64: u64 = bwt_index.try_into().unwrap();
+
+    // The diropqlz layout keeps the sentinel in the BWT string at the primary index
+    bwt_encoded.insert(bwt_index, 0);
+    let bwt_encoded: String = bwt_encoded.iter().map(|&b| b as char).collect();
+
+    let mtf_encoded = crate::compressor::mtf::encode(&bwt_encoded, &String::from("\0dilopqr"))
+        .expect("diropql programs only contain characters of the mtf alphabet");
+
+    let rle_encoded = crate::compressor::rle::encode(&mtf_encoded);
+
+    // Code lengths are limited so that they fit in the nibbles of the compact header
+    let mut huffman_encoded = BitWriter::new();
+    let huffman_bitlens = crate::compressor::huffman::encode_with_limit(&rle_encoded, &mut huffman_encoded, NIBBLE_MAX_LEN);
+
+    // The message is stored in whole bytes; the offset counts the padding bits of the last byte
+    let msg_offset = huffman_encoded.padding();
+    let huffman_encoded = huffman_encoded.into_bytes();
+
     // Define the metadata struct
     let meta_data = DpqlzMeta {
-        mlen: msg_len,
+        mlen: huffman_encoded.len() as u64,
         moffset: msg_offset,
         bwt_idx: bwt_index_u64,
+        plen: program_len as u64,
         huff_bitlens: huffman_bitlens,
+        checksum: Some(Checksum::new(data, &huffman_encoded)),
+        ..Default::default()
     };
 
-    // Use the write_meta function to convert the compressed diropql file to a diropqlz file
-    let dpqlz = write_meta(&meta_data, &huffman_encoded);
-
-    return dpqlz;
+    return Ok((meta_data, huffman_encoded));
 }
 
-// read function takes in a diropqlz file and returns the original message
-
-pub fn read(prog: &String) -> String {
-    // Use the read_meta function to convert the diropqlz file to the compressed diropql file
-    let (meta_data, huffman_encoded) = read_meta(prog);
+// expand function reverses compress and returns the original binary data
 
+fn expand(meta_data: &DpqlzMeta, huffman_encoded: &[u8]) -> Result<Vec<u8>> {
     // Decompress the diropql file using huffman, rle, mtf, and bwt decode functions
-    let huffman_decoded = crate::compressor::huffman::decode(&huffman_encoded, &meta_data.huff_bitlens);
+    let mut huffman_reader = BitReader::with_padding(huffman_encoded, meta_data.moffset);
+    let huffman_decoded = crate::compressor::huffman::decode(&mut huffman_reader, &meta_data.huff_bitlens)?;
+
+    let rle_decoded = expand_rle(meta_data, &huffman_decoded)?;
 
-    let rle_decoded = crate::compressor::rle::decode(&huffman_decoded);
+    let mtf_decoded = crate::compressor::mtf::decode(&rle_decoded, &String::from("\0dilopqr"))?;
 
-    let mtf_decoded = crate::compressor::mtf::decode(&rle_decoded, &String::from("\0dilopqr"));
+    // Remove the sentinel stored at the primary index before inverting the BWT
+    let mut bwt_encoded = mtf_decoded.into_bytes();
+    let bwt_index_err = Error::BwtIndexOutOfRange { index: meta_data.bwt_idx, len: bwt_encoded.len() };
+
+    let bwt_idx_usize: usize = meta_data.bwt_idx.try_into().map_err(|_| bwt_index_err.clone())?;
+
+    if bwt_encoded.get(bwt_idx_usize) != Some(&0) {
+        return Err(bwt_index_err);
+    }
 
-	let bwt_idx_usize = meta_data.bwt_idx.try_into().unwrap();
-    let dpql = crate::compressor::bwt::decode(&mtf_decoded, bwt_idx_usize);
+    bwt_encoded.remove(bwt_idx_usize);
+    let dpql = crate::compressor::bwt::decode(&bwt_encoded, bwt_idx_usize)?;
+    let dpql = String::from_utf8(dpql).map_err(|_| Error::InvalidUtf8)?;
 
     // Convert the diropql program to the original message
-    let text = crate::dpql::read(&dpql);
+    let data = crate::dpql::read_bytes(&dpql)?;
+    verify_message(meta_data, &data)?;
 
-    return text;
+    return Ok(data);
 }
 
-// write_meta function converts a compressed diropql program to a diropqlz program
+/* expand_rle function reverses the RLE stage. Its output may not be longer than the program length stored in the
+header and must be exactly that long; version 0 files do not store it and are only held to MAX_PROGRAM_LEN */
 
-pub fn write_meta(meta: &DpqlzMeta, prog: &Vec<u8>) -> String {
+fn expand_rle(meta: &DpqlzMeta, huffman_decoded: &[u16]) -> Result<Vec<u16>> {
+    if meta.plen > MAX_PROGRAM_LEN as u64 {
+        return Err(Error::ProgramTooLong { len: meta.plen });
+    }
 
-    // Convert obfuscated message's length from u64 to a vector of eight u8 elements
-    let msg_len_u64 = meta.mlen;
-    let mut msg_len_u8: Vec<u8> = msg_len_u64.to_be_bytes().to_vec();
+    let max_len = if meta.plen == 0 { MAX_PROGRAM_LEN } else { meta.plen as usize };
+    let rle_decoded = crate::compressor::rle::decode(huffman_decoded, max_len)?;
 
-    // Append obfuscated message's offset to the obfuscated message's length vector
-    let msg_offset = meta.moffset;
-    msg_len_u8.push(msg_offset);
+    if meta.plen != 0 && rle_decoded.len() != max_len {
+        return Err(Error::ProgramLengthMismatch { expected: meta.plen, found: rle_decoded.len() });
+    }
+
+    return Ok(rle_decoded);
+}
 
-    // Convert obfuscated message's bwt index from u64 to a vector of eight u8 elements
-    let msg_bwt_idx_u64 = meta.bwt_idx;
-    let mut msg_bwt_idx_u8: Vec<u8> = msg_bwt_idx_u64.to_be_bytes().to_vec();
+// associated_data function returns the header fields that the cipher authenticates along with the payload
 
-    // Insert 6 zeros at the end of the obfuscated message's canonical Huffman codebook
-    let mut msg_huff_bitlens = meta.huff_bitlens.clone();
+fn associated_data(meta: &DpqlzMeta) -> Vec<u8> {
+    let mut aad: Vec<u8> = fields_bytes(meta);
+    varint::write_u64(meta.plen, &mut aad);
+    aad.extend(&meta.huff_bitlens);
 
-    for _n in 0..6 {
-        msg_huff_bitlens.push(0);
+    return aad;
+}
+
+// write_meta function converts a compressed diropql program, packed into bytes, to a diropqlz program; it panics
+// if meta.fec_parity is 255, which the write functions reject and read_meta never returns
+
+pub fn write_meta(meta: &DpqlzMeta, prog: &[u8]) -> String {
+    let flags = meta_flags(meta);
+
+    // Combine the header and the packed obfuscated message into a single vector
+    let mut msg: Vec<u8> = header_bytes(meta, flags);
+    msg.extend(prog);
+
+    // The Reed–Solomon code protects the header and the message; the format prefix it depends on is written
+    // FORMAT_COPIES times instead, the first copy ending with the parity count after the version and flags
+    if flags & FLAG_FEC != 0 {
+        let mut protected: Vec<u8> = vec![meta.fec_parity, 0];
+
+        for _ in 1..FORMAT_COPIES {
+            protected.extend([FORMAT_VERSION, flags, meta.fec_parity, 0]);
+        }
+
+        protected.extend(fec::encode(&msg, meta.fec_parity).expect("the write functions reject a parity of 255"));
+
+        msg = protected;
+    }
+    
+    return write_container(flags, &msg);
+}
+
+// meta_flags function returns the feature flags that describe the metadata of a single-block file
+
+fn meta_flags(meta: &DpqlzMeta) -> u8 {
+    // Codewords longer than a nibble need the self-describing codebook header
+    let mut flags = if meta.huff_bitlens.iter().any(|&len| len > NIBBLE_MAX_LEN) { FLAG_CODEBOOK } else { 0 };
+
+    if meta.checksum.is_some() {
+        flags |= FLAG_CHECKSUM;
+    }
+
+    if meta.fec_parity > 0 {
+        flags |= FLAG_FEC;
     }
 
-    // Append the offset zeros at the end of the obfuscated message
-    let mut prog_b = prog.clone();
+    if meta.encryption.is_some() {
+        flags |= FLAG_ENCRYPTED;
+    }
+
+    if meta.recipients.is_some() {
+        flags |= FLAG_RECIPIENTS;
+    }
 
-    for _n in 0..msg_offset {
-        prog_b.push(0);
+    if meta.signature.is_some() {
+        flags |= FLAG_SIGNED;
     }
 
-    // Convert the obfuscated message from binary to a vector of u8 elements
-    let mut b_str = String::new();
-    let mut prog_u8: Vec<u8> = Vec::new();
+    return flags;
+}
+
+// read_meta function converts a diropqlz program to a compressed diropql program packed into bytes; the last
+// meta.moffset bits are padding
+
+pub fn read_meta(prog: &str) -> Result<(DpqlzMeta, Vec<u8>)> {
+    let (format, decoded_msg) = read_container(prog)?;
+
+    return split_meta(format, &decoded_msg);
+}
+
+// Format version, feature flags and Reed–Solomon parity stored after the magic string
 
-    for bit in &prog_b {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Format {
+    version: u8,
+    flags: u8,
+    parity: u8,
+}
+
+// split_meta function splits the decoded bytes of a single-block diropqlz file into the metadata and the
+// packed compressed diropql program
+
+fn split_meta(format: Format, decoded_msg: &[u8]) -> Result<(DpqlzMeta, Vec<u8>)> {
+    match format.version {
+        LEGACY_VERSION => {
+            if decoded_msg.len() < HEADER_LEN {
+                return Err(Error::TruncatedHeader { expected: HEADER_LEN, found: decoded_msg.len() });
+            }
+
+            // Split the vector into the header fields and the obfuscated message
+            let meta_data = parse_header(&decoded_msg[..HEADER_LEN]);
+            let cmpr_dpql = decoded_msg[HEADER_LEN..].to_vec();
 
-        if b_str.len() != 8 {
-            b_str.push_str(&bit.to_string());
+            return Ok((meta_data, cmpr_dpql));
         }
 
-        else {
-            prog_u8.push(u8::from_str_radix(&b_str, 2).unwrap());
-            b_str = String::from(&bit.to_string());
+        FORMAT_VERSION => {
+            if format.flags & FLAG_SHARE != 0 {
+                return Err(Error::ShareMismatch { share: true });
+            }
+
+            if format.flags & FLAG_BLOCKS != 0 {
+                return Err(Error::BlockModeMismatch { blocks: true });
+            }
+
+            let (mut meta_data, header_len) = parse_meta_header(decoded_msg, format.flags)?;
+            meta_data.fec_parity = format.parity;
+
+            let cmpr_dpql = decoded_msg[header_len..].to_vec();
+            verify_payload(&meta_data, &cmpr_dpql)?;
+
+            return Ok((meta_data, cmpr_dpql));
         }
-    }
 
-    if prog_b.len() != 0 {
-        prog_u8.push(u8::from_str_radix(&b_str, 2).unwrap());
+        version => return Err(Error::UnsupportedVersion(version)),
     }
+}
 
-    // Combine all the vector of u8 elements into a single vector
-    let mut msg: Vec<u8> = Vec::new();
-    msg.extend(msg_len_u8);
-    msg.extend(msg_bwt_idx_u8);
-    msg.extend(msg_huff_bitlens);
-    msg.extend(prog_u8);
-    
-    // Encode the message using the Base85 encode function
-    let mut encoded_msg = encode(&msg);
-    encoded_msg.insert_str(0, &String::from("DIROPQLZ"));   // Prepend the magic string
+/* write_container function Base85-encodes the format version, the feature flags and the message, and prepends
+the magic string and the format marker */
+
+fn write_container(flags: u8, msg: &[u8]) -> String {
+    let mut encoded_msg = String::from("DIROPQLZ");   // Prepend the magic string
+    encoded_msg.push(FORMAT_MARKER);
+
+    let mut format_msg: Vec<u8> = vec![FORMAT_VERSION, flags];
+    format_msg.extend(msg);
+
+    encoded_msg.push_str(&encode(&format_msg));
 
     return encoded_msg;
 }
 
-// read_meta function converts a diropqlz program to a compressed diropql program
+/* read_container function reverses write_container and returns the format and the decoded message; a file
+without the format marker is a version 0 file. Unknown versions and unreadable flag combinations are rejected
+here, so every reader dispatches on a format it understands, and Reed–Solomon encoded files are corrected here.
+A Reed–Solomon encoded file is recognised by two equal copies of its format prefix, so a mangled first copy is
+outvoted by the other two */
 
-pub fn read_meta(prog: &String) -> (DpqlzMeta, Vec<u8>) {
+fn read_container(prog: &str) -> Result<(Format, Vec<u8>)> {
     // Remove the unnecessary characters and the magic string prepended from the diropqlz program
-    let mut dpqlz = prog.clone();
-    let mut ignore = String::new();
+    let dpqlz = strip_magic(prog)?;
+
+    let dpqlz = match dpqlz.strip_prefix(FORMAT_MARKER) {
+        Some(rest) => rest,
+        None => return Ok((Format { version: LEGACY_VERSION, flags: 0, parity: 0 }, decode_base85(dpqlz)?)),
+    };
 
-    for c in dpqlz.chars() {
+    let decoded_msg = decode_base85(dpqlz)?;
 
-        if ignore.contains(&String::from("DIROPQLZ")) {
-            dpqlz = dpqlz.replace(&ignore, &String::from(""));
-            break;
-        }
+    let format = match vote_format(&decoded_msg) {
+        Some(format) => format,
 
-        ignore.push(c);
+        None if decoded_msg.len() < 2 => return Err(Error::TruncatedHeader { expected: 2, found: decoded_msg.len() }),
+        None => Format { version: decoded_msg[0], flags: decoded_msg[1], parity: 0 },
+    };
+
+    // Version 0 is never marked
+    if format.version != FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion(format.version));
     }
 
-    // Decodes the diropqlz program which returns the vector of bytes containing the metadata and the obfuscated message
-    let decoded_msg = decode(&dpqlz).unwrap();
+    // Every bit of the flags byte is assigned, so only combinations this build cannot read are rejected
+    if format.flags & FLAG_SHARE != 0 && format.flags & !SHARE_FLAGS != 0 {
+        return Err(Error::UnsupportedFlags(format.flags & !SHARE_FLAGS));
+    }
 
-    // Split the vector into the different struct fields and the obfuscated message
-    let mut msg_len_u8: Vec<u8> = Vec::new();
-    let mut msg_offset: u8 = 0;
-    let mut msg_bwt_idx_u8: Vec<u8> = Vec::new();
-    let mut msg_huff_bitlens: Vec<u8> = Vec::new();
-    let mut prog_u8: Vec<u8> = Vec::new();
-    
-    for i in 0..decoded_msg.len() {
+    if format.flags & FLAG_FEC == 0 {
+        return Ok((format, decoded_msg[2..].to_vec()));
+    }
 
-        if i <= 7 {
-            msg_len_u8.push(decoded_msg[i]);
-        }
+    // Without two matching copies of its format prefix, a Reed–Solomon encoded file is too damaged to read
+    if format.parity == 0 {
+        let expected = FORMAT_COPIES * FORMAT_COPY_LEN;
 
-        else if i == 8 {
-            msg_offset = decoded_msg[i];
+        if decoded_msg.len() < expected {
+            return Err(Error::TruncatedHeader { expected, found: decoded_msg.len() });
         }
 
-        else if i > 8 && i <= 16 {
-            msg_bwt_idx_u8.push(decoded_msg[i]);
-        }
+        return Err(Error::UncorrectableCodeword { index: 0 });
+    }
 
-        else if i > 16 && i <= 26 {
-            msg_huff_bitlens.push(decoded_msg[i]);
+    return Ok((format, fec::decode(&decoded_msg[FORMAT_COPIES * FORMAT_COPY_LEN..], format.parity)?));
+}
+
+// vote_format function returns the format of a Reed–Solomon encoded file that at least two copies of its format
+// prefix agree on, or None if the file is not one
+
+fn vote_format(decoded_msg: &[u8]) -> Option<Format> {
+    let prefix = decoded_msg.get(..FORMAT_COPIES * FORMAT_COPY_LEN)?;
+    let copies: Vec<&[u8]> = prefix.chunks(FORMAT_COPY_LEN).collect();
+
+    let winner = copies.iter().enumerate()
+        .find(|&(i, copy)| copies[i + 1..].contains(copy))
+        .map(|(_, copy)| *copy)?;
+
+    // The headers of other files would need two equal groups that also read as a sensible prefix
+    match *winner {
+        [version, flags, parity, 0] if flags & FLAG_FEC != 0 && (1..=fec::MAX_PARITY).contains(&parity) => {
+            return Some(Format { version, flags, parity });
         }
 
-        else if i > 32 {
-            prog_u8.push(decoded_msg[i]);
+        _ => return None,
+    }
+}
+
+// strip_magic function returns the text after the DIROPQLZ magic string, ignoring anything before it
+
+fn strip_magic(prog: &str) -> Result<&str> {
+    match prog.find("DIROPQLZ") {
+        Some(start) => return Ok(&prog[start + "DIROPQLZ".len()..]),
+        None => return Err(Error::BadMagic),
+    }
+}
+
+// fill_random function fills the bytes from the operating system random number generator
+
+fn fill_random(bytes: &mut [u8]) {
+    getrandom::getrandom(bytes).expect("the operating system random number generator is available");
+}
+
+// random_bytes function returns N bytes from the operating system random number generator
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    fill_random(&mut bytes);
+
+    return bytes;
+}
+
+// decode_base85 function decodes the text after the magic string without letting the base85 crate panic
+
+fn decode_base85(text: &str) -> Result<Vec<u8>> {
+    check_base85(text)?;
+
+    return decode(text).ok_or(Error::InvalidBase85);
+}
+
+// fields_bytes function converts the mlen, moffset and bwt_idx fields to FIELDS_LEN bytes
+
+fn fields_bytes(meta: &DpqlzMeta) -> Vec<u8> {
+    // Convert obfuscated message's length from u64 to a vector of eight u8 elements
+    let mut fields: Vec<u8> = meta.mlen.to_be_bytes().to_vec();
+
+    // Append obfuscated message's offset to the obfuscated message's length vector
+    fields.push(meta.moffset);
+
+    // Append obfuscated message's bwt index as eight u8 elements
+    fields.extend(meta.bwt_idx.to_be_bytes());
+
+    return fields;
+}
+
+// parse_fields function converts the first FIELDS_LEN bytes of a header and the codebook to the metadata
+
+fn parse_fields(header: &[u8], huff_bitlens: Vec<u8>) -> DpqlzMeta {
+    let mut msg_len_u8 = [0u8; 8];
+    msg_len_u8.copy_from_slice(&header[0..8]);
+
+    let mut msg_bwt_idx_u8 = [0u8; 8];
+    msg_bwt_idx_u8.copy_from_slice(&header[9..17]);
+
+    return DpqlzMeta {
+        mlen: u64::from_be_bytes(msg_len_u8),
+        moffset: header[8],
+        bwt_idx: u64::from_be_bytes(msg_bwt_idx_u8),
+        huff_bitlens,
+        ..Default::default()
+    };
+}
+
+// parse_header function converts the fixed HEADER_LEN byte header back to the metadata
+
+fn parse_header(header: &[u8]) -> DpqlzMeta {
+    return parse_fields(header, header[FIELDS_LEN..FIELDS_LEN + LEGACY_CODEBOOK_LEN].to_vec());
+}
+
+/* header_bytes function converts the metadata to the header selected by the FLAG_CODEBOOK feature flag, followed
+by the checksums if FLAG_CHECKSUM is set, the encryption parameters if FLAG_ENCRYPTED is set, the recipient
+stanzas if FLAG_RECIPIENTS is set and the signature if FLAG_SIGNED is set; the signature always comes last */
+
+fn header_bytes(meta: &DpqlzMeta, flags: u8) -> Vec<u8> {
+    let mut header = if flags & FLAG_CODEBOOK != 0 { codebook_header_bytes(meta) } else { compact_header_bytes(meta) };
+
+    if flags & FLAG_CHECKSUM != 0 {
+        let checksum = meta.checksum.expect("FLAG_CHECKSUM is only set for metadata with checksums");
+
+        header.extend(checksum.message.to_be_bytes());
+        header.extend(checksum.payload.to_be_bytes());
+    }
+
+    if flags & FLAG_ENCRYPTED != 0 {
+        let encryption = meta.encryption.expect("FLAG_ENCRYPTED is only set for metadata with encryption parameters");
+
+        header.extend(crypt::header_bytes(&encryption));
+    }
+
+    if flags & FLAG_RECIPIENTS != 0 {
+        let recipients = meta.recipients.as_ref().expect("FLAG_RECIPIENTS is only set for metadata with recipients");
+
+        header.extend(keys::recipients_bytes(recipients));
+    }
+
+    if flags & FLAG_SIGNED != 0 {
+        let signature = meta.signature.expect("FLAG_SIGNED is only set for metadata with a signature");
+
+        header.extend(keys::signature_bytes(&signature));
+    }
+
+    return header;
+}
+
+// parse_meta_header function reads a header written by header_bytes and returns the metadata and the length of
+// the header in bytes
+
+fn parse_meta_header(header: &[u8], flags: u8) -> Result<(DpqlzMeta, usize)> {
+    let (mut meta_data, mut header_len) = if flags & FLAG_CODEBOOK != 0 {
+        parse_codebook_header(header)?
+    }
+    else {
+        parse_compact_header(header)?
+    };
+
+    if flags & FLAG_CHECKSUM != 0 {
+        if header.len() - header_len < 8 {
+            return Err(Error::TruncatedHeader { expected: header_len + 8, found: header.len() });
         }
+
+        let mut message = [0u8; 4];
+        message.copy_from_slice(&header[header_len..header_len + 4]);
+
+        let mut payload = [0u8; 4];
+        payload.copy_from_slice(&header[header_len + 4..header_len + 8]);
+
+        meta_data.checksum = Some(Checksum { message: u32::from_be_bytes(message), payload: u32::from_be_bytes(payload) });
+        header_len += 8;
     }
 
-    // Convert the vector of bytes of the mlen field to u64
-    let mut msg_len_u64: u64 = 0;
+    // Truncation inside the later fields is reported relative to the whole header
+    if flags & FLAG_ENCRYPTED != 0 {
+        let (encryption, encryption_len) = crypt::parse_header(&header[header_len..])
+            .map_err(|err| shift_truncation(err, header_len, header.len()))?;
 
-    for &byte in &msg_len_u8 {
-        msg_len_u64 = (msg_len_u64 << 8) | u64::from(byte);
+        meta_data.encryption = Some(encryption);
+        header_len += encryption_len;
     }
 
-    // Convert the vector of bytes of the bwt_idx field to u64
-    let mut msg_bwt_idx_u64: u64 = 0;
+    if flags & FLAG_RECIPIENTS != 0 {
+        let (recipients, recipients_len) = keys::parse_recipients(&header[header_len..])
+            .map_err(|err| shift_truncation(err, header_len, header.len()))?;
 
-    for &byte in &msg_bwt_idx_u8 {
-        msg_bwt_idx_u64 = (msg_bwt_idx_u64 << 8) | u64::from(byte);
+        meta_data.recipients = Some(recipients);
+        header_len += recipients_len;
     }
 
-    // The fields of the metadata is decoded
-    let meta_data = DpqlzMeta {
-        mlen: msg_len_u64,
-        moffset: msg_offset,
-        bwt_idx: msg_bwt_idx_u64,
-        huff_bitlens: msg_huff_bitlens,
+    if flags & FLAG_SIGNED != 0 {
+        let (signature, signature_len) = keys::parse_signature(&header[header_len..])
+            .map_err(|err| shift_truncation(err, header_len, header.len()))?;
+
+        meta_data.signature = Some(signature);
+        header_len += signature_len;
+    }
+
+    return Ok((meta_data, header_len));
+}
+
+// shift_truncation function converts a truncation error of a field that starts at offset to one of the whole header
+
+fn shift_truncation(err: Error, offset: usize, found: usize) -> Error {
+    match err {
+        Error::TruncatedHeader { expected, .. } => return Error::TruncatedHeader { expected: offset + expected, found },
+        err => return err,
+    }
+}
+
+// verify_payload function compares the packed payload with its stored checksum, if there is one
+
+fn verify_payload(meta: &DpqlzMeta, payload: &[u8]) -> Result<()> {
+    match meta.checksum {
+        Some(checksum) if checksum.payload != crc32fast::hash(payload) => return Err(Error::ChecksumMismatch { payload: true }),
+        _ => return Ok(()),
+    }
+}
+
+// verify_message function compares the decoded message with its stored checksum, if there is one
+
+fn verify_message(meta: &DpqlzMeta, data: &[u8]) -> Result<()> {
+    match meta.checksum {
+        Some(checksum) if checksum.message != crc32fast::hash(data) => return Err(Error::ChecksumMismatch { payload: false }),
+        _ => return Ok(()),
+    }
+}
+
+/* compact_header_bytes function converts the metadata to the compact header: mlen as a varint, moffset as
+one byte, bwt_idx and plen as varints, the number of code lengths as a varint and the code lengths packed two
+per byte, high nibble first; every code length must fit in a nibble */
+
+fn compact_header_bytes(meta: &DpqlzMeta) -> Vec<u8> {
+    let mut header: Vec<u8> = Vec::new();
+
+    varint::write_u64(meta.mlen, &mut header);
+    header.push(meta.moffset);
+    varint::write_u64(meta.bwt_idx, &mut header);
+    varint::write_u64(meta.plen, &mut header);
+    varint::write_u64(meta.huff_bitlens.len() as u64, &mut header);
+
+    for pair in meta.huff_bitlens.chunks(2) {
+        let high = pair[0];
+        let low = pair.get(1).copied().unwrap_or(0);
+        header.push((high << 4) | low);
+    }
+
+    return header;
+}
+
+// parse_compact_header function reads a header written by compact_header_bytes and returns the metadata and
+// the length of the header in bytes
+
+fn parse_compact_header(header: &[u8]) -> Result<(DpqlzMeta, usize)> {
+    let mut pos: usize = 0;
+
+    let mlen = varint::read_u64(header, &mut pos)?;
+
+    let moffset = match header.get(pos) {
+        Some(&moffset) => moffset,
+        None => return Err(Error::TruncatedHeader { expected: pos + 1, found: header.len() }),
     };
+    pos += 1;
 
-    // Convert the vector of bytes of the obfuscated message to a single binary string
-    let mut prog_b = String::new();
-    
-    for &byte in &prog_u8 {
-        let b_str = format!("{:08b}", byte);
-        prog_b.push_str(&b_str);
+    let bwt_idx = varint::read_u64(header, &mut pos)?;
+    let plen = varint::read_u64(header, &mut pos)?;
+    let symbol_count = varint::read_u64(header, &mut pos)?;
+
+    // Two code lengths per byte
+    let codebook_len = usize::try_from(symbol_count.div_ceil(2)).unwrap_or(usize::MAX);
+    if header.len() - pos < codebook_len {
+        return Err(Error::TruncatedHeader { expected: pos.saturating_add(codebook_len), found: header.len() });
     }
 
-    // Append each bit in the binary string to a vector of bits
-    let mut cmpr_dpql: Vec<u8> = Vec::new();
+    let mut huff_bitlens: Vec<u8> = Vec::new();
+    for &byte in &header[pos..pos + codebook_len] {
+        huff_bitlens.push(byte >> 4);
+        huff_bitlens.push(byte & 0x0F);
+    }
+    huff_bitlens.truncate(symbol_count as usize);
+    pos += codebook_len;
 
-    for c in prog_b.chars() {
-        
-        if c == '1' {
-            cmpr_dpql.push(1);
-        }
+    let meta_data = DpqlzMeta { mlen, moffset, bwt_idx, plen, huff_bitlens, ..Default::default() };
+
+    return Ok((meta_data, pos));
+}
+
+// codebook_header_bytes function converts the metadata to a header of the fixed fields and plen as a varint that
+// ends with the self-describing codebook of huffman::write_codebook, padded to whole bytes
+
+fn codebook_header_bytes(meta: &DpqlzMeta) -> Vec<u8> {
+    let mut codebook = BitWriter::new();
+    crate::compressor::huffman::write_codebook(&meta.huff_bitlens, &mut codebook);
+
+    let mut header: Vec<u8> = fields_bytes(meta);
+    varint::write_u64(meta.plen, &mut header);
+    header.extend(codebook.into_bytes());
+
+    return header;
+}
 
-        else {
-            cmpr_dpql.push(0);
+// parse_codebook_header function reads a header written by codebook_header_bytes and returns the metadata and
+// the length of the header in bytes
+
+fn parse_codebook_header(header: &[u8]) -> Result<(DpqlzMeta, usize)> {
+    if header.len() < FIELDS_LEN {
+        return Err(Error::TruncatedHeader { expected: FIELDS_LEN, found: header.len() });
+    }
+
+    let mut pos = FIELDS_LEN;
+    let plen = varint::read_u64(header, &mut pos)?;
+
+    let codebook_bytes = &header[pos..];
+    let mut codebook = BitReader::new(codebook_bytes);
+    let huff_bitlens = crate::compressor::huffman::read_codebook(&mut codebook)?;
+
+    let codebook_len = (codebook_bytes.len() * 8 - codebook.remaining()).div_ceil(8);
+
+    let mut meta_data = parse_fields(header, huff_bitlens);
+    meta_data.plen = plen;
+
+    return Ok((meta_data, pos + codebook_len));
+}
+
+// check_base85 function rejects the strings that make the base85 decode function panic
+
+fn check_base85(text: &str) -> Result<()> {
+    // A single leftover character cannot encode a byte
+    if text.len() % 5 == 1 {
+        return Err(Error::InvalidBase85);
+    }
+
+    let mut digits: Vec<u64> = Vec::new();
+
+    for c in text.chars() {
+
+        match BASE85_ALPHABET.find(c) {
+            Some(digit) => digits.push(digit as u64),
+            None => return Err(Error::InvalidBase85),
         }
     }
 
-    // Remove the offset bits and the compressed diropql message is decoded
-    for _n in 0..msg_offset {
-        cmpr_dpql.pop();
+    // Every group of five digits must fit in a u32; the decoder pads a short last group with 126
+    for group in digits.chunks(5) {
+        let mut value: u64 = 0;
+
+        for i in 0..5 {
+            value = value * 85 + group.get(i).copied().unwrap_or(126);
+        }
+
+        if value > u64::from(u32::MAX) {
+            return Err(Error::InvalidBase85);
+        }
     }
 
-    return (meta_data, cmpr_dpql);
+    return Ok(());
 }
 
-// Metadata struct used for encoding and decoding of diropqlz program
+// Submodule block
+pub mod block;
+
+// Submodule varint
+mod varint;
+
+// Submodule fec
+mod fec;
 
+// Submodule crypt
+pub mod crypt;
+
+// Submodule keys
+pub mod keys;
+
+// Submodule shamir
+pub mod shamir;
+
+/* Metadata struct used for encoding and decoding of diropqlz program; new features add fields, so code outside
+this crate starts from DpqlzMeta::default() and sets the fields it needs */
+
+#[derive(Default)]
+#[non_exhaustive]
 pub struct DpqlzMeta {
     pub mlen: u64,
     pub moffset: u8,
     pub bwt_idx: u64,
-    pub huff_bitlens: Vec<u8>,
+    pub plen: u64,     // Length of the BWT string the payload expands to; version 0 files store none and read it as 0
+    pub huff_bitlens: Vec<u8>,     // Codeword length of every symbol; any alphabet size up to 65535
+    pub checksum: Option<Checksum>,     // Checksums verified on read; version 0 files have none
+    pub fec_parity: u8,     // Reed–Solomon check bytes per 255-byte codeword; 0 disables error correction
+    pub encryption: Option<crypt::Encryption>,     // Key derivation and cipher parameters of an encrypted payload
+    pub recipients: Option<keys::Recipients>,     // Wrapped message keys of a payload encrypted to public keys
+    pub signature: Option<keys::Signature>,     // Sender's public key and signature over the header and payload
+}
+
+// CRC32 checksums of the original message and of the packed payload
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checksum {
+    pub message: u32,
+    pub payload: u32,
+}
+
+impl Checksum {
+    // new function computes the checksums of a message and of its packed payload
+
+    pub fn new(message: &[u8], payload: &[u8]) -> Self {
+        return Checksum { message: crc32fast::hash(message), payload: crc32fast::hash(payload) };
+    }
 }
 
 #[cfg(test)]
@@ -271,18 +913,18 @@ mod zip_tests {
 	
 		let message = String::from("");
 		let encoded = write(&message);	   
-		let expected = String::from("DIROPQLZ00000000012LJ#70000000961000000000000000000");
+		let expected = String::from("DIROPQLZ.0R#aD009aR0000000000(gN>|00");
 	   
 		assert_eq!(expected, encoded);
     }
 
     #[test]
     fn read_pt1_empty() {
-        // Takes in a dpqlz file; should ouptut empty string
+        // Takes in a dpqlz file in the legacy fixed layout; should ouptut empty string
 		// write_pt_empty in reverse
 		
 		let encoded = String::from("DIROPQLZ00000000012LJ#70000000961000000000000000000");		
-		let message = read(&encoded);
+		let message = read(&encoded).unwrap();
 		let expected = String::from("");
 		
 		assert_eq!(expected, message);
@@ -296,7 +938,7 @@ mod zip_tests {
 		
 		let message = String::from("Hello world!");
 		let encoded = write(&message);
-		let expected = String::from("DIROPQLZ000000000P1ONa400003F9HGp0s{sF1^@*B000000Pk>8&=d<vN`g=*1u04>8~So+F}|CziXZ");
+		let expected = String::from("DIROPQLZ.0R$8UxdHwH3L*kDKmZ$s8I`A7z`ye;LW@*1qjJ3pCWHcks#mCrHoy");
 		
 		assert_eq!(expected, encoded);
 	}
@@ -306,9 +948,10 @@ mod zip_tests {
 		// The Hello World test example ni sir
 		// I tried reverse engineering it and it seems legit
 		// Theoretically, this should work even if the specific dpql implementations aren't the same
+		// The file uses the legacy fixed layout, which read must keep accepting
 		
 		let encoded = String::from("DIROPQLZ000000000P1ONa400003F9HGp0s{sF1^@*B000000Pk>8&=d<vN`g=*1u04>8~So+F}|CziXZ");
-		let message = read(&encoded);
+		let message = read(&encoded).unwrap();
 		let expected = String::from("Hello world!");
 
 		assert_eq!(expected, message);
@@ -320,7 +963,7 @@ mod zip_tests {
 		// It should ignore the characters before the magic string DIROPQLZ
 		
 		let encoded = String::from("qwerty asdfDIROPQLZ000000000P1ONa400003F9HGp0s{sF1^@*B000000Pk>8&=d<vN`g=*1u04>8~So+F}|CziXZ");
-		let message = read(&encoded);
+		let message = read(&encoded).unwrap();
 		let expected = String::from("Hello world!");
 
 		assert_eq!(expected, message);
@@ -333,7 +976,7 @@ mod zip_tests {
 		
 		let original = String::from("");
 		let encoded = write(&original);
-		let decoded = read(&encoded);
+		let decoded = read(&encoded).unwrap();
 		
 		assert_eq!(original, decoded);
 	}
@@ -344,7 +987,7 @@ mod zip_tests {
 		
 		let original = String::from("the quick brown fox jumps over the lazy dog. THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG");
 		let encoded = write(&original);
-		let decoded = read(&encoded);
+		let decoded = read(&encoded).unwrap();
 		
 		assert_eq!(original, decoded);
 	}
@@ -355,7 +998,7 @@ mod zip_tests {
 		
 		let original = String::from(" !'\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~"); 
 		let encoded = write(&original);
-		let decoded = read(&encoded);
+		let decoded = read(&encoded).unwrap();
 		
 		assert_eq!(original, decoded);
 	}
@@ -366,7 +1009,7 @@ mod zip_tests {
 		
 		let original = String::from("Carriage Return:\rSpace: Newline:\nTab:\t");
 		let encoded = write(&original);
-		let decoded = read(&encoded);
+		let decoded = read(&encoded).unwrap();
 		
 		assert_eq!(original, decoded);
 	}
@@ -377,7 +1020,7 @@ mod zip_tests {
 		
 		let original = String::from("Hello world!");
 		let encoded = write(&original);
-		let decoded = read(&encoded);
+		let decoded = read(&encoded).unwrap();
 		
 		assert_eq!(original, decoded);
 	}
@@ -391,6 +1034,7 @@ mod zip_tests {
 			moffset: 1,
 			bwt_idx: 7,
 			huff_bitlens: vec![1,0,0,0,0,0,0,0,0,0],
+			..Default::default()
 		};
 		
 		assert_eq!(5, new_meta.mlen);
@@ -409,6 +1053,7 @@ mod zip_tests {
 			moffset: 0,
 			bwt_idx: 0,
 			huff_bitlens: vec![0,0,0,0,0,0,0,0,0,0],
+			..Default::default()
 		};
 		
 		let prog: Vec<u8> = vec![];		
@@ -416,15 +1061,16 @@ mod zip_tests {
 		
 		/* Notes on the expected return string
 		
-		There are a total of 33 bytes (264 bits) for the prepended metadata
-		In this test case, all of them are 0. The message itself is an empty string. 
-		Additionally, no bits are appended since total output is already divisible by 8.
+		The format version 1 and the flags 0 come first. The compact header is mlen, moffset,
+		bwt_idx and plen as the bytes 0 0 0 0,
+		the number of code lengths 10 and the 10 code lengths packed into 5 bytes of nibbles.
+		The message itself is an empty string.
 		
-		Thus, we have 264 bits of 0. This long string of zeros are then encoded to base 85.
-		The magic string "DIROPQLZ" is then prepended and this is now the final output.		
+		These 17 bytes are encoded to base 85. The magic string "DIROPQLZ" and the format
+		marker "." are then prepended and this is now the final output.		
 		*/
 		
-		let expected = String::from("DIROPQLZ000000000000000000000000000000000000000000");
+		let expected = String::from("DIROPQLZ.0RR91000UA00000");
 		
 		assert_eq!(expected, encoded)
 	}
@@ -442,11 +1088,12 @@ mod zip_tests {
 			moffset: 0,
 			bwt_idx: 0,
 			huff_bitlens: vec![0,0,0,0,0,0,0,0,0,0],
+			..Default::default()
 		};
 		
 		let expected: Vec<u8> = vec![];	// The expected decoded is an empty vector
 				
-		let (meta, decoded) = read_meta(&prog); // Input program into read_meta
+		let (meta, decoded) = read_meta(&prog).unwrap(); // Input program into read_meta
 		
 		
 		assert_eq!(expected, decoded);		// Assert correctness of decoded message
@@ -462,28 +1109,29 @@ mod zip_tests {
 	fn write_meta_pt2_unit() {
 		// Unit test: inputs a vector with a single element of 0; 
 		
-		let prog: Vec<u8> = vec![0];		// Single element of 0
+		let prog: Vec<u8> = vec![0];		// Single bit of 0, packed into a byte
 		
 		let meta = DpqlzMeta {
 			mlen: 1,						// Length in bytes of message
 			moffset: 7,						// Ignore 7 bits at the end of message
 			bwt_idx: 1,									// Index is 0
 			huff_bitlens: vec![1,0,0,0,0,0,0,0,0,0],	// huffman codebook
+			..Default::default()
 		};		
 		
 		let encoded = write_meta(&meta, &prog);
 		
 		/* Notes on the expected return string
 		
-		There are a total of 33 bytes (264 bits) for the prepended metadata.
-		Not all metadata bits are zero. The message itself is 1 bit of 0.
-		7 bits will need to be appended to the end of the mesesage to make it 8-divisible.
+		The format version 1 and the flags 0 take 2 bytes. The compact header takes 10 bytes: mlen 1, moffset 7, bwt_idx 1, plen 0,
+		the number of code lengths 10 and the nibbles 0x10 0x00 0x00 0x00 0x00.
+		The message itself is 1 bit of 0, padded with 7 bits to a whole byte.
 		
-		Thus, we have 272 bits total (34 bytes). This long string is encoded to base 85
-		and a magic string "DIROPQLZ" is prepended and this is now the final output.
+		Thus, we have 13 bytes total. This is encoded to base 85 and the magic string
+		"DIROPQLZ" and the format marker "." are prepended; this is now the final output.
 		*/
 		
-		let expected = String::from("DIROPQLZ00000000012LJ#7000000RaF2000000000000000000");		
+		let expected = String::from("DIROPQLZ.0RRC90RRdR0000000");		
 
 		assert_eq!(expected, encoded)
 	}
@@ -502,9 +1150,10 @@ mod zip_tests {
 			moffset: 7,						
 			bwt_idx: 1,								
 			huff_bitlens: vec![1,0,0,0,0,0,0,0,0,0],	
+			..Default::default()
 		};
 		
-		let (meta, decoded) = read_meta(&prog); 	// Input program into read_meta		
+		let (meta, decoded) = read_meta(&prog).unwrap(); 	// Input program into read_meta		
 		let expected: Vec<u8> = vec![0];				// The expected decoded is a vector with 0
 			
 		assert_eq!(expected, decoded);					// Assert correctness of decoded message
@@ -520,18 +1169,20 @@ mod zip_tests {
 	fn write_meta_pt3() {
 		// Arbritrary test: input is 54 binary bits long (append 2 zeros to end)
 		
-		let prog: Vec<u8> = vec![1,1,1,1,1,1,1,1,0,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,0,1,1,1,1,1,1,0,1,1,1,1,1,0,1,1,1,1,0,1,1,1,0,1,1,0,1,0,0];
+		// 11111111 01111111 11111111 10111111 01111101 11101110 110100(00), packed into bytes
+		let prog: Vec<u8> = vec![0xFF,0x7F,0xFF,0xBF,0x7D,0xEE,0xD0];
 		
 		let meta = DpqlzMeta {
 			mlen: 7,
 			moffset: 2,
 			bwt_idx: 9,
-			huff_bitlens: vec![1,1,0,1,1,1,1,1,1,1]
+			huff_bitlens: vec![1,1,0,1,1,1,1,1,1,1],
+			..Default::default()
 		};
 		
 		let encoded = write_meta(&meta, &prog);
 		
-		let expected = String::from("DIROPQLZ00000000070ssI2000002>}5B0RaI40RaI3000000RMmgzkTk|");
+		let expected = String::from("DIROPQLZ.0RRUA2>=Qa0TB@q|9}6#eeTc");
 									
 		assert_eq!(expected, encoded);
 	}
@@ -547,11 +1198,13 @@ mod zip_tests {
 			moffset: 2,
 			bwt_idx: 9,
 			huff_bitlens: vec![1,1,0,1,1,1,1,1,1,1],
+			..Default::default()
 		};
 		
-		let expected: Vec<u8> = vec![1,1,1,1,1,1,1,1,0,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,0,1,1,1,1,1,1,0,1,1,1,1,1,0,1,1,1,1,0,1,1,1,0,1,1,0,1,0,0];
+		// The packed bytes come back with the 2 padding bits still in place
+		let expected: Vec<u8> = vec![0xFF,0x7F,0xFF,0xBF,0x7D,0xEE,0xD0];
 		
-		let (meta, decoded) = read_meta(&prog); 			
+		let (meta, decoded) = read_meta(&prog).unwrap(); 			
 		
 		assert_eq!(expected, decoded);	
 		
@@ -566,18 +1219,19 @@ mod zip_tests {
 		// Arbritrary test: input is 16 bits long: 1111111100000000
 		// No need to put extra bits
 		
-		let prog: Vec<u8> = vec![1,1,1,1,1,1,1,1,0,0,0,0,0,0,0,0];
+		let prog: Vec<u8> = vec![0xFF,0x00];
 		
 		let meta = DpqlzMeta {
 			mlen: 2,
 			moffset: 0,
 			bwt_idx: 300,
 			huff_bitlens: vec![0,0,0,8,8,0,0,0,0,0],
+			..Default::default()
 		};
 		
 		let encoded = write_meta(&meta, &prog);
 		
-		let expected = String::from("DIROPQLZ00000000020000000001EC2ui2nYZG00000000000RI3");
+		let expected = String::from("DIROPQLZ.0RRF3tO5WE00@8p0RI3");
 		
 		assert_eq!(expected, encoded);
 	}
@@ -593,11 +1247,12 @@ mod zip_tests {
 			moffset: 0,
 			bwt_idx: 300,
 			huff_bitlens: vec![0,0,0,8,8,0,0,0,0,0],
+			..Default::default()
 		};
 		
-		let expected: Vec<u8> = vec![1,1,1,1,1,1,1,1,0,0,0,0,0,0,0,0]; 
+		let expected: Vec<u8> = vec![0xFF,0x00]; 
 		
-		let (meta, decoded) = read_meta(&prog); 			
+		let (meta, decoded) = read_meta(&prog).unwrap(); 			
 		
 		assert_eq!(expected, decoded);	
 		
@@ -620,10 +1275,11 @@ mod zip_tests {
 			moffset: 0,
 			bwt_idx: 0,
 			huff_bitlens: vec![0,0,0,0,0,0,0,0,0,0],
+			..Default::default()
 		};
 		
 		let expected: Vec<u8> = vec![];					
-		let (meta, decoded) = read_meta(&prog); 		
+		let (meta, decoded) = read_meta(&prog).unwrap(); 		
 		
 		assert_eq!(expected, decoded);		
 		
@@ -632,4 +1288,329 @@ mod zip_tests {
 		assert_eq!(expected_meta.bwt_idx, meta.bwt_idx);
 		assert_eq!(expected_meta.huff_bitlens, meta.huff_bitlens);
 	}
-} 
\ No newline at end of file
+	#[test]
+	fn read_meta_pt6_bad_magic() {
+		// The magic string DIROPQLZ is missing; read_meta should return an error instead of panicking
+		
+		let prog = String::from("DIROPQL000000000000000000000000000000000000000000");
+		
+		assert_eq!(Err(Error::BadMagic), read_meta(&prog).map(|_| ()));
+	}
+	
+	#[test]
+	fn read_meta_pt7_invalid_base85() {
+		// Double quotes are not part of the Base85 alphabet
+		
+		let prog = String::from("DIROPQLZ00000\"00000000000000000000000000000000000000");
+		
+		assert_eq!(Err(Error::InvalidBase85), read_meta(&prog).map(|_| ()));
+	}
+	
+	#[test]
+	fn read_meta_pt8_truncated() {
+		// Only 8 bytes are encoded after the magic string; the header needs 33
+		
+		let prog = String::from("DIROPQLZ0000000000");
+		let expected = Error::TruncatedHeader { expected: 33, found: 8 };
+		
+		assert_eq!(Err(expected), read_meta(&prog).map(|_| ()));
+	}
+	
+	#[test]
+	fn write_read_meta_pt9_codebook() {
+		// A codebook of 258 symbols does not fit in the fixed header and is stored self-describing
+		
+		let text: Vec<u16> = (0..=255).chain(0..100).collect();
+		let mut writer = BitWriter::new();
+		let huff_bitlens = crate::compressor::huffman::encode(&text, &mut writer);
+		
+		let meta = DpqlzMeta {
+			mlen: writer.bit_len().div_ceil(8) as u64,
+			moffset: writer.padding(),
+			bwt_idx: 42,
+			huff_bitlens: huff_bitlens.clone(),
+			..Default::default()
+		};
+		
+		let prog = writer.into_bytes();
+		let encoded = write_meta(&meta, &prog);
+		let (meta, decoded) = read_meta(&encoded).unwrap();
+		
+		assert!(encoded.starts_with("DIROPQLZ."));
+		assert_eq!(258, meta.huff_bitlens.len());
+		assert_eq!(huff_bitlens, meta.huff_bitlens);
+		assert_eq!(42, meta.bwt_idx);
+		assert_eq!(prog, decoded);
+		
+		let mut reader = BitReader::with_padding(&decoded, meta.moffset);
+		assert_eq!(text, crate::compressor::huffman::decode(&mut reader, &meta.huff_bitlens).unwrap());
+	}
+	
+	#[test]
+	fn read_meta_pt10_version() {
+		// Unknown versions, and version 0 behind the format marker, are rejected
+		
+		assert_eq!(Err(Error::UnsupportedVersion(9)), read_meta(&format!("DIROPQLZ.{}", encode(&[9; 40]))).map(|_| ()));
+		assert_eq!(Err(Error::UnsupportedVersion(0)), read_meta(&format!("DIROPQLZ.{}", encode(&[0; 40]))).map(|_| ()));
+		assert_eq!(Err(Error::TruncatedHeader { expected: 2, found: 1 }), read_meta(&format!("DIROPQLZ.{}", encode(&[1]))).map(|_| ()));
+		
+		let error = read(&format!("DIROPQLZ.{}", encode(&[2, 0, 0]))).unwrap_err();
+		assert_eq!("unsupported diropqlz format version 2", error.to_string());
+	}
+	
+	#[test]
+	fn read_meta_pt11_compact() {
+		// The compact header of write_meta_pt3 comes back with the same fields
+		
+		let (meta, decoded) = read_meta("DIROPQLZ.0RRUA2>=Qa0TB@q|9}6#eeTc").unwrap();
+		
+		assert_eq!(vec![0xFF,0x7F,0xFF,0xBF,0x7D,0xEE,0xD0], decoded);
+		assert_eq!(7, meta.mlen);
+		assert_eq!(2, meta.moffset);
+		assert_eq!(9, meta.bwt_idx);
+		assert_eq!(vec![1,1,0,1,1,1,1,1,1,1], meta.huff_bitlens);
+	}
+	
+	#[test]
+	fn write_meta_pt5_size() {
+		// "Hello world!" is now mostly message instead of header
+		
+		let legacy = String::from("DIROPQLZ000000000P1ONa400003F9HGp0s{sF1^@*B000000Pk>8&=d<vN`g=*1u04>8~So+F}|CziXZ");
+		let encoded = write("Hello world!");
+		
+		// The checksums add 8 bytes; the rest of the header stays small
+		let (mut meta, prog) = read_meta(&encoded).unwrap();
+		meta.checksum = None;
+		
+		assert!(write_meta(&meta, &prog).len() < legacy.len() * 2 / 3);
+		// The stored program length costs one more byte, so the full string lands just above 3/4
+		assert!(encoded.len() < legacy.len() * 4 / 5);
+		assert_eq!(read(&legacy), read(&encoded));
+	}
+	
+	#[test]
+	fn read_meta_pt12_truncated_compact() {
+		// The compact header of write_meta_pt4 cut off inside its codebook
+		
+		let meta = DpqlzMeta { mlen: 2, moffset: 0, bwt_idx: 300, huff_bitlens: vec![0,0,0,8,8,0,0,0,0,0], ..Default::default() };
+		let header = compact_header_bytes(&meta);
+		let encoded = write_container(0, &header[..header.len() - 1]);
+		
+		assert_eq!(Err(Error::TruncatedHeader { expected: 11, found: 10 }), read_meta(&encoded).map(|_| ()));
+	}
+	
+	#[test]
+	fn read_meta_pt13_flags() {
+		// Flags this build does not know are rejected; the version and flags lead every marked file
+		
+		let meta = DpqlzMeta { mlen: 0, moffset: 0, bwt_idx: 0, huff_bitlens: vec![1,1], ..Default::default() };
+		let header = compact_header_bytes(&meta);
+		
+		assert_eq!(Err(Error::UnsupportedFlags(FLAG_CODEBOOK)), read_meta(&write_container(FLAG_SHARE | FLAG_CODEBOOK, &header)).map(|_| ()));
+		assert_eq!(Ok(vec![FORMAT_VERSION, 0]), decode_base85(&write_meta(&meta, &[])["DIROPQLZ.".len()..]).map(|msg| msg[..2].to_vec()));
+	}
+	
+	#[test]
+	fn read_pt4_bwt_index() {
+		// Same program as write_meta_pt2_unit but the bwt index points past the single bwt character
+		
+		let meta = DpqlzMeta {
+			mlen: 1,
+			moffset: 7,
+			bwt_idx: 5,
+			huff_bitlens: vec![1,0,0,0,0,0,0,0,0,0],
+			..Default::default()
+		};
+		
+		let encoded = write_meta(&meta, &[0]);
+		let expected = Error::BwtIndexOutOfRange { index: 5, len: 1 };
+		
+		assert_eq!(Err(expected), read(&encoded));
+	}
+	
+	#[test]
+	fn read_pt5_codebook() {
+		// The codebook is empty, so no bit of the message can be decoded
+		
+		let meta = DpqlzMeta {
+			mlen: 1,
+			moffset: 7,
+			bwt_idx: 0,
+			huff_bitlens: vec![0,0,0,0,0,0,0,0,0,0],
+			..Default::default()
+		};
+		
+		let encoded = write_meta(&meta, &[0]);
+		
+		assert_eq!(Err(Error::CodebookViolation), read(&encoded));
+	}
+	
+	#[test]
+	fn write_read_pt6_bytes() {
+		// Binary data such as images must round-trip bit-exactly
+		
+		let mut original: Vec<u8> = (0..=255).collect();
+		original.extend(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+		let encoded = write_bytes(&original);
+		let decoded = read_bytes(&encoded).unwrap();
+		
+		assert_eq!(original, decoded);
+	}
+	
+	#[test]
+	fn write_read_pt7_unicode() {
+		// Non-ASCII text is obfuscated as UTF-8 and comes back unchanged
+		
+		let original = String::from("¡Hola, señor! Привет, мир! 👋");
+		let encoded = write(&original);
+		let decoded = read(&encoded).unwrap();
+		
+		assert_eq!(original, decoded);
+	}
+	
+	#[test]
+	fn read_pt6_checksum() {
+		// A character flipped in the payload is caught before decoding instead of yielding garbage
+		
+		let encoded = write("Hello, World!");
+		
+		for pos in encoded.len() - 10..encoded.len() - 2 {
+			let mut corrupted = encoded.clone().into_bytes();
+			corrupted[pos] = if corrupted[pos] == b'0' { b'1' } else { b'0' };
+			let corrupted = String::from_utf8(corrupted).unwrap();
+			
+			let result = read(&corrupted);
+			assert!(matches!(result, Err(Error::ChecksumMismatch { payload: true }) | Err(Error::InvalidBase85)), "{:?}", result);
+		}
+		
+		// A payload that matches its checksum but decodes to another message fails the message checksum
+		let (mut meta, prog) = read_meta(&encoded).unwrap();
+		meta.checksum = Some(Checksum::new(b"Hello, World?", &prog));
+		
+		assert_eq!(Err(Error::ChecksumMismatch { payload: false }), read(&write_meta(&meta, &prog)));
+		
+		// Files without checksums are still read
+		meta.checksum = None;
+		assert_eq!(Ok(String::from("Hello, World!")), read(&write_meta(&meta, &prog)));
+	}
+	
+	#[test]
+	fn read_pt7_fec() {
+		// Mangled characters are corrected by the Reed–Solomon code; one Base85 character spoils up to 4 bytes
+		
+		let message = "The quick brown fox jumps over the lazy dog. ".repeat(12);
+		let encoded = write_bytes_with_parity(message.as_bytes(), 8).unwrap();
+		
+		let (meta, _) = read_meta(&encoded).unwrap();
+		assert_eq!(8, meta.fec_parity);
+		assert_eq!(Err(Error::InvalidRedundancy(255)), write_bytes_with_parity(b"hi", 255));
+		assert_eq!(Err(Error::InvalidRedundancy(255)), write_bytes_encrypted_with_parity(b"hi", "passphrase", 255));
+		
+		// One character of every 255 bytes is replaced, the first in a copy of the format prefix
+		let mut mangled = encoded.clone().into_bytes();
+		for pos in (20..mangled.len()).step_by(320) {
+			mangled[pos] = if mangled[pos] == b'0' { b'1' } else { b'0' };
+		}
+		let mangled = String::from_utf8(mangled).unwrap();
+		
+		assert_ne!(encoded, mangled);
+		assert_eq!(Ok(message.clone()), read(&mangled));
+		
+		// A mangled first Base85 group, which holds the version, flags and parity, is outvoted by the other copies
+		let mangle = |positions: &[usize]| {
+			let mut mangled = encoded.clone().into_bytes();
+			for &pos in positions {
+				mangled[pos] = if mangled[pos] == b'0' { b'1' } else { b'0' };
+			}
+			String::from_utf8(mangled).unwrap()
+		};
+		
+		for pos in 9..14 {
+			assert_eq!(Ok(message.clone()), read(&mangle(&[pos])));
+		}
+		
+		// Two mangled copies leave nothing to vote with
+		assert!(read(&mangle(&[10, 15])).is_err());
+		
+		// Without the Reed–Solomon code the same damage is only detected
+		let plain = write(&message);
+		let mut mangled = plain.into_bytes();
+		mangled[40] = if mangled[40] == b'0' { b'1' } else { b'0' };
+		
+		assert!(read(&String::from_utf8(mangled).unwrap()).is_err());
+	}
+	
+	#[test]
+	fn read_pt8_encrypted() {
+		// The payload opens only with the right passphrase; cheap Argon2 costs keep the test fast
+		
+		let message = "Meet me at the usual place at noon.";
+		let encoded = write_sealed(message.as_bytes(), "correct horse", crypt::Encryption::with_cost(64, 1), 0).unwrap();
+		let (meta, sealed) = read_meta(&encoded).unwrap();
+		
+		assert!(meta.encryption.is_some());
+		assert_eq!(None, meta.checksum);
+		assert_eq!(meta.mlen, sealed.len() as u64);
+		assert_eq!(Ok(String::from(message)), read_encrypted(&encoded, "correct horse"));
+		assert_eq!(Err(Error::DecryptionFailed), read_encrypted(&encoded, "correct horse battery"));
+		
+		// Reading needs a passphrase, and a passphrase needs an encrypted file
+		assert_eq!(Err(Error::EncryptionMismatch { encrypted: true }), read(&encoded));
+		assert_eq!(Err(Error::EncryptionMismatch { encrypted: false }), read_encrypted(&write(message), "correct horse"));
+		
+		// Changing an authenticated header field is detected as tampering
+		let mut tampered = meta;
+		tampered.bwt_idx += 1;
+		assert_eq!(Err(Error::DecryptionFailed), read_encrypted(&write_meta(&tampered, &sealed), "correct horse"));
+		
+		// Costs raised in the header are refused before the key is derived
+		let (mut forged, _) = read_meta(&encoded).unwrap();
+		forged.encryption = forged.encryption.map(|encryption| crypt::Encryption { iterations: u32::MAX, ..encryption });
+		assert_eq!(Err(Error::ExcessiveCost { memory_kib: 64, iterations: u32::MAX }), read_encrypted(&write_meta(&forged, &sealed), "correct horse"));
+	}
+	
+	#[test]
+	fn write_read_pt9_encrypted() {
+		// The recommended Argon2 costs and a Reed–Solomon code work together; every file gets a fresh salt
+		
+		let data: Vec<u8> = (0..=255).collect();
+		let encoded = write_bytes_encrypted_with_parity(&data, "passphrase", 16).unwrap();
+		
+		assert_ne!(Ok(encoded.clone()), write_bytes_encrypted_with_parity(&data, "passphrase", 16));
+		assert_eq!(Ok(data), read_bytes_encrypted(&encoded, "passphrase"));
+	}
+	
+	#[test]
+	fn read_pt9_program_length() {
+		// The stored program length bounds the RLE stage, which must then fill it exactly
+		
+		let encoded = write("Hello, World!");
+		let (mut meta, prog) = read_meta(&encoded).unwrap();
+		let plen = meta.plen;
+		
+		meta.plen = plen + 1;
+		assert_eq!(Err(Error::ProgramLengthMismatch { expected: plen + 1, found: plen as usize }), read(&write_meta(&meta, &prog)));
+		
+		meta.plen = plen - 1;
+		assert_eq!(Err(Error::InvalidRunLength), read(&write_meta(&meta, &prog)));
+		
+		meta.plen = MAX_PROGRAM_LEN as u64 + 1;
+		assert_eq!(Err(Error::ProgramTooLong { len: MAX_PROGRAM_LEN as u64 + 1 }), read(&write_meta(&meta, &prog)));
+	}
+	
+	#[test]
+	fn write_read_pt10_long() {
+		// A message whose program exceeds MAX_PROGRAM_LEN is written in block mode, or refused where that is not possible
+		
+		// Alternating 0x80 and 0 costs 128 commands and an o per byte, the most any byte can take
+		let data: Vec<u8> = (0..=block::MAX_BLOCK_SIZE).map(|i| if i % 2 == 0 { 0x80 } else { 0x00 }).collect();
+		
+		assert!(matches!(compress(&data), Err(Error::ProgramTooLong { .. })));
+		assert!(matches!(write_bytes_with_parity(&data, 0), Err(Error::ProgramTooLong { .. })));
+		
+		let encoded = write_bytes(&data);
+		
+		assert_eq!(Err(Error::BlockModeMismatch { blocks: true }), read_meta(&encoded).map(|_| ()));
+		assert_eq!(Ok(data), read_bytes(&encoded));
+	}
+}
@@ -0,0 +1,321 @@
+/* Block mode of the diropqlz container: the message is split into blocks of block_size bytes and every block
+is converted to a diropql program and run through BWT, MTF, RLE and Huffman on its own, in parallel.
+
+A block file sets the FLAG_BLOCKS feature flag; after the format version and flags it stores
+    block count (varint) | per block: compact header | payload (mlen bytes)
+Every block header stores the bwt_idx, the program length and the payload length of its block, so the blocks can be located
+and decoded in parallel. */
+
+use super::{expand_rle, header_bytes, parse_meta_header, read_container, varint, verify_message, verify_payload, write_container};
+use super::{Checksum, DpqlzMeta, Format, FLAG_BLOCKS, FLAG_CHECKSUM, FLAG_ENCRYPTED, FLAG_RECIPIENTS, FLAG_SIGNED, MAX_PROGRAM_LEN, NIBBLE_MAX_LEN};
+use crate::compressor::bitio::{BitReader, BitWriter};
+use crate::error::{Error, Result};
+
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// MTF alphabet of a block; the block BWT is sentinel-free so it needs no \0 symbol
+const BLOCK_ALPHABET: &str = "dilopqr";
+
+// Largest number of message bytes per block; a byte becomes at most 128 i or d commands and one o command, so the
+// program of a block never exceeds MAX_PROGRAM_LEN
+pub const MAX_BLOCK_SIZE: usize = MAX_PROGRAM_LEN / 129;
+
+// Options of the block mode
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockOptions {
+    // Number of message bytes per block; sizes above MAX_BLOCK_SIZE write blocks of MAX_BLOCK_SIZE bytes
+    pub block_size: usize,
+
+    // Number of worker threads used to compress or decompress the blocks
+    pub threads: usize,
+}
+
+impl Default for BlockOptions {
+    fn default() -> Self {
+        return BlockOptions {
+            block_size: 100_000,
+            threads: thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1),
+        };
+    }
+}
+
+// write_bytes function converts binary data to a block mode diropqlz file
+
+pub fn write_bytes(data: &[u8], options: &BlockOptions) -> String {
+    let chunks: Vec<&[u8]> = data.chunks(options.block_size.clamp(1, MAX_BLOCK_SIZE)).collect();
+    let blocks = parallel_map(&chunks, options.threads, |chunk| compress_block(chunk));
+
+    // Combine the block count and every block into a single vector
+    let mut msg: Vec<u8> = Vec::new();
+    varint::write_u64(blocks.len() as u64, &mut msg);
+
+    for (meta, payload) in &blocks {
+        msg.extend(header_bytes(meta, FLAG_BLOCKS | FLAG_CHECKSUM));
+        msg.extend(payload);
+    }
+
+    return write_container(FLAG_BLOCKS | FLAG_CHECKSUM, &msg);
+}
+
+// read_bytes function converts a block mode diropqlz file back to the original binary data
+
+pub fn read_bytes(prog: &str, options: &BlockOptions) -> Result<Vec<u8>> {
+    return expand_blocks(&read_blocks(prog)?, options);
+}
+
+// read_blocks function splits a block mode diropqlz file into the metadata and packed payload of every block
+
+pub fn read_blocks(prog: &str) -> Result<Vec<(DpqlzMeta, Vec<u8>)>> {
+    let (format, decoded_msg) = read_container(prog)?;
+
+    if format.flags & FLAG_BLOCKS == 0 {
+        return Err(Error::BlockModeMismatch { blocks: false });
+    }
+
+    return split_blocks(format, &decoded_msg);
+}
+
+// split_blocks function splits the decoded bytes of a block mode diropqlz file into its blocks
+
+pub(super) fn split_blocks(format: Format, decoded_msg: &[u8]) -> Result<Vec<(DpqlzMeta, Vec<u8>)>> {
+    // Block mode has no encryption
+    if format.flags & (FLAG_ENCRYPTED | FLAG_RECIPIENTS) != 0 {
+        return Err(Error::EncryptionMismatch { encrypted: true });
+    }
+
+    // Block mode is never signed, so a signature could not be verified
+    if format.flags & FLAG_SIGNED != 0 {
+        return Err(Error::UnsupportedFlags(FLAG_SIGNED));
+    }
+
+    let mut pos: usize = 0;
+    let block_count = varint::read_u64(decoded_msg, &mut pos)?;
+
+    // Walk the block headers to find where every payload starts
+    let mut blocks: Vec<(DpqlzMeta, Vec<u8>)> = Vec::new();
+
+    for index in 0..block_count as usize {
+        let (meta, header_len) = parse_meta_header(&decoded_msg[pos..], format.flags)?;
+        let rest = &decoded_msg[pos + header_len..];
+        let payload_len = usize::try_from(meta.mlen).unwrap_or(usize::MAX);
+
+        if rest.len() < payload_len {
+            return Err(Error::TruncatedBlock { index });
+        }
+
+        let payload = rest[..payload_len].to_vec();
+        verify_payload(&meta, &payload)?;
+        pos += header_len + payload_len;
+        blocks.push((meta, payload));
+    }
+
+    if pos != decoded_msg.len() {
+        return Err(Error::TrailingBlockData { len: decoded_msg.len() - pos });
+    }
+
+    return Ok(blocks);
+}
+
+// expand_blocks function decodes the blocks in parallel and concatenates them
+
+pub(super) fn expand_blocks(blocks: &[(DpqlzMeta, Vec<u8>)], options: &BlockOptions) -> Result<Vec<u8>> {
+    let decoded = parallel_map(blocks, options.threads, |(meta, payload)| expand_block(meta, payload));
+
+    let mut data: Vec<u8> = Vec::new();
+
+    for block in decoded {
+        data.extend(block?);
+    }
+
+    return Ok(data);
+}
+
+// compress_block function runs one block of the message through diropql, BWT, MTF, RLE and Huffman
+
+fn compress_block(data: &[u8]) -> (DpqlzMeta, Vec<u8>) {
+    let dpql = crate::dpql::write_bytes(data);
+
+    let (bwt_encoded, bwt_index) = crate::compressor::bwt::encode(dpql.as_bytes());
+    let bwt_encoded: String = bwt_encoded.iter().map(|&b| b as char).collect();
+
+    let mtf_encoded = crate::compressor::mtf::encode(&bwt_encoded, BLOCK_ALPHABET)
+        .expect("diropql programs only contain characters of the mtf alphabet");
+
+    let rle_encoded = crate::compressor::rle::encode(&mtf_encoded);
+
+    let mut huffman_encoded = BitWriter::new();
+    let huffman_bitlens = crate::compressor::huffman::encode_with_limit(&rle_encoded, &mut huffman_encoded, NIBBLE_MAX_LEN);
+
+    // The last byte of the payload is padded with msg_offset zero bits
+    let msg_offset = huffman_encoded.padding();
+    let payload = huffman_encoded.into_bytes();
+
+    let meta_data = DpqlzMeta {
+        mlen: payload.len() as u64,
+        moffset: msg_offset,
+        bwt_idx: bwt_index as u64,
+        plen: bwt_encoded.len() as u64,
+        huff_bitlens: huffman_bitlens,
+        checksum: Some(Checksum::new(data, &payload)),
+        ..Default::default()
+    };
+
+    return (meta_data, payload);
+}
+
+// expand_block function reverses compress_block and returns the bytes of one block of the message
+
+fn expand_block(meta: &DpqlzMeta, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut huffman_reader = BitReader::with_padding(payload, meta.moffset);
+
+    let huffman_decoded = crate::compressor::huffman::decode(&mut huffman_reader, &meta.huff_bitlens)?;
+
+    let rle_decoded = expand_rle(meta, &huffman_decoded)?;
+
+    let mtf_decoded = crate::compressor::mtf::decode(&rle_decoded, BLOCK_ALPHABET)?;
+
+    let bwt_encoded = mtf_decoded.into_bytes();
+    let bwt_idx_usize: usize = meta.bwt_idx.try_into()
+        .map_err(|_| Error::BwtIndexOutOfRange { index: meta.bwt_idx, len: bwt_encoded.len() })?;
+
+    let dpql = crate::compressor::bwt::decode(&bwt_encoded, bwt_idx_usize)?;
+    let dpql = String::from_utf8(dpql).map_err(|_| Error::InvalidUtf8)?;
+
+    let data = crate::dpql::read_bytes(&dpql)?;
+    verify_message(meta, &data)?;
+
+    return Ok(data);
+}
+
+// parallel_map function applies f to every item on up to `threads` worker threads and returns the results in order
+
+fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = threads.clamp(1, items.len().max(1));
+
+    if workers == 1 {
+        return items.iter().map(&f).collect();
+    }
+
+    // Every worker takes the next unclaimed item until none are left
+    let next = AtomicUsize::new(0);
+
+    let finished: Vec<Vec<(usize, R)>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done: Vec<(usize, R)> = Vec::new();
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+
+                        if i >= items.len() {
+                            break;
+                        }
+
+                        done.push((i, f(&items[i])));
+                    }
+
+                    done
+                })
+            })
+            .collect();
+
+        handles.into_iter().map(|handle| handle.join().expect("block worker panicked")).collect()
+    });
+
+    let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+
+    for (i, result) in finished.into_iter().flatten() {
+        results[i] = Some(result);
+    }
+
+    return results.into_iter().map(|result| result.expect("every block is processed once")).collect();
+}
+
+#[cfg(test)]
+mod block_tests {
+    use super::*;
+    use super::super::FLAG_SHARE;
+
+    #[test]
+    fn write_read_pt1_blocks() {
+        // A message split over many small blocks decodes on any number of threads
+
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let options = BlockOptions { block_size: 300, threads: 4 };
+        let encoded = write_bytes(&data, &options);
+
+        assert_eq!(17, read_blocks(&encoded).unwrap().len());
+        assert_eq!(data, read_bytes(&encoded, &options).unwrap());
+        assert_eq!(data, read_bytes(&encoded, &BlockOptions { block_size: 1, threads: 1 }).unwrap());
+    }
+
+    #[test]
+    fn write_read_pt2_threads() {
+        // The output does not depend on the number of threads
+
+        let data = "the quick brown fox jumps over the lazy dog. ".repeat(40);
+        let single = write_bytes(data.as_bytes(), &BlockOptions { block_size: 128, threads: 1 });
+        let multi = write_bytes(data.as_bytes(), &BlockOptions { block_size: 128, threads: 8 });
+
+        assert_eq!(single, multi);
+    }
+
+    #[test]
+    fn write_read_pt3_empty() {
+        // An empty message has no blocks
+
+        let encoded = write_bytes(b"", &BlockOptions::default());
+
+        assert!(read_blocks(&encoded).unwrap().is_empty());
+        assert_eq!(Vec::<u8>::new(), read_bytes(&encoded, &BlockOptions::default()).unwrap());
+    }
+
+    #[test]
+    fn read_pt1_dispatch() {
+        // zip::read detects the block flag; single-block files are not block files
+
+        let encoded = write_bytes("Hello, World!".as_bytes(), &BlockOptions { block_size: 4, threads: 2 });
+        let single = super::super::write("Hello, World!");
+
+        assert_eq!(Ok(String::from("Hello, World!")), super::super::read(&encoded));
+        assert_eq!(Err(Error::BlockModeMismatch { blocks: false }), read_blocks(&single).map(|_| ()));
+        assert_eq!(Err(Error::BlockModeMismatch { blocks: true }), super::super::read_meta(&encoded).map(|_| ()));
+    }
+
+    #[test]
+    fn read_pt2_errors() {
+        // Unknown flags and payloads cut short are rejected
+
+        let encoded = write_container(FLAG_BLOCKS | FLAG_SHARE, &[0]);
+        assert_eq!(Err(Error::UnsupportedFlags(FLAG_BLOCKS)), read_blocks(&encoded).map(|_| ()));
+
+        let meta = DpqlzMeta { mlen: 10, moffset: 0, bwt_idx: 0, huff_bitlens: vec![1, 1], ..Default::default() };
+        let mut msg: Vec<u8> = vec![1];
+        msg.extend(header_bytes(&meta, FLAG_BLOCKS));
+        msg.extend([0xAA; 9]);
+        let encoded = write_container(FLAG_BLOCKS, &msg);
+        assert_eq!(Err(Error::TruncatedBlock { index: 0 }), read_blocks(&encoded).map(|_| ()));
+    }
+
+    #[test]
+    fn read_pt3_signed_trailing() {
+        // Block files never carry a signature, and bytes after the last block are not ignored
+
+        let encoded = write_bytes(b"Hello, World!", &BlockOptions { block_size: 4, threads: 1 });
+        let (format, mut msg) = read_container(&encoded).unwrap();
+
+        assert_eq!(Err(Error::UnsupportedFlags(FLAG_SIGNED)), read_blocks(&write_container(format.flags | FLAG_SIGNED, &msg)).map(|_| ()));
+
+        msg.extend([0xAA; 3]);
+        assert_eq!(Err(Error::TrailingBlockData { len: 3 }), read_blocks(&write_container(format.flags, &msg)).map(|_| ()));
+    }
+}
@@ -0,0 +1,175 @@
+/* Passphrase encryption of the diropqlz payload.
+
+The key is derived from the passphrase with Argon2id and a random salt, and the packed payload is sealed with
+ChaCha20-Poly1305. An encrypted file sets the FLAG_ENCRYPTED feature flag; after the checksums its header stores
+    salt (16 bytes) | nonce (12 bytes) | Argon2 memory cost in KiB (varint) | Argon2 passes (varint)
+The header fields are authenticated together with the payload, so a wrong passphrase and any tampering are both
+reported as Error::DecryptionFailed. The costs come from the untrusted header, so keys are only derived with costs
+up to MAX_COST_FACTOR times the defaults. */
+
+use super::{random_bytes, varint};
+use crate::error::{Error, Result};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+// Size in bytes of the Argon2 salt
+pub const SALT_LEN: usize = 16;
+
+// Size in bytes of the ChaCha20-Poly1305 nonce
+pub const NONCE_LEN: usize = 12;
+
+// Size in bytes of the Poly1305 tag appended to the payload
+pub const TAG_LEN: usize = 16;
+
+// Largest multiple of the default Argon2id memory cost and passes a key is derived with
+pub const MAX_COST_FACTOR: u32 = 16;
+
+// Key derivation and cipher parameters of an encrypted payload
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Encryption {
+    pub salt: [u8; SALT_LEN],
+    pub nonce: [u8; NONCE_LEN],
+    pub memory_kib: u32,     // Argon2id memory cost
+    pub iterations: u32,     // Argon2id number of passes
+}
+
+impl Encryption {
+    // new function picks a fresh random salt and nonce and the recommended Argon2id costs
+
+    pub fn new() -> Self {
+        return Encryption::with_cost(Params::DEFAULT_M_COST, Params::DEFAULT_T_COST);
+    }
+
+    // with_cost function picks a fresh random salt and nonce and the given Argon2id costs
+
+    pub fn with_cost(memory_kib: u32, iterations: u32) -> Self {
+        return Encryption { salt: random_bytes(), nonce: random_bytes(), memory_kib, iterations };
+    }
+}
+
+impl Default for Encryption {
+    fn default() -> Self {
+        return Encryption::new();
+    }
+}
+
+// seal function encrypts the payload and appends the tag; aad is authenticated but not encrypted
+
+pub fn seal(encryption: &Encryption, passphrase: &str, aad: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(&derive_key(encryption, passphrase)?);
+    let nonce = Nonce::from_slice(&encryption.nonce);
+
+    return cipher.encrypt(nonce, Payload { msg: payload, aad }).map_err(|_| Error::DecryptionFailed);
+}
+
+// open function reverses seal; a wrong passphrase or any change to the ciphertext or aad fails
+
+pub fn open(encryption: &Encryption, passphrase: &str, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(&derive_key(encryption, passphrase)?);
+    let nonce = Nonce::from_slice(&encryption.nonce);
+
+    return cipher.decrypt(nonce, Payload { msg: ciphertext, aad }).map_err(|_| Error::DecryptionFailed);
+}
+
+// derive_key function stretches the passphrase into a 256-bit key with Argon2id
+
+fn derive_key(encryption: &Encryption, passphrase: &str) -> Result<Key> {
+    // A forged header could otherwise ask for terabytes of memory or billions of passes
+    if encryption.memory_kib > Params::DEFAULT_M_COST * MAX_COST_FACTOR || encryption.iterations > Params::DEFAULT_T_COST * MAX_COST_FACTOR {
+        return Err(Error::ExcessiveCost { memory_kib: encryption.memory_kib, iterations: encryption.iterations });
+    }
+
+    let params = Params::new(encryption.memory_kib, encryption.iterations, 1, Some(32)).map_err(|_| Error::DecryptionFailed)?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+    let mut key = Key::default();
+    argon2.hash_password_into(passphrase.as_bytes(), &encryption.salt, &mut key).map_err(|_| Error::DecryptionFailed)?;
+
+    return Ok(key);
+}
+
+// header_bytes function converts the encryption parameters to the bytes stored in the header
+
+pub(super) fn header_bytes(encryption: &Encryption) -> Vec<u8> {
+    let mut header: Vec<u8> = encryption.salt.to_vec();
+    header.extend(encryption.nonce);
+
+    varint::write_u64(u64::from(encryption.memory_kib), &mut header);
+    varint::write_u64(u64::from(encryption.iterations), &mut header);
+
+    return header;
+}
+
+// parse_header function reads the encryption parameters written by header_bytes and returns them with their
+// length in bytes
+
+pub(super) fn parse_header(header: &[u8]) -> Result<(Encryption, usize)> {
+    if header.len() < SALT_LEN + NONCE_LEN {
+        return Err(Error::TruncatedHeader { expected: SALT_LEN + NONCE_LEN, found: header.len() });
+    }
+
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&header[..SALT_LEN]);
+
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&header[SALT_LEN..SALT_LEN + NONCE_LEN]);
+
+    let mut pos = SALT_LEN + NONCE_LEN;
+    let memory_kib = u32::try_from(varint::read_u64(header, &mut pos)?).map_err(|_| Error::InvalidVarint)?;
+    let iterations = u32::try_from(varint::read_u64(header, &mut pos)?).map_err(|_| Error::InvalidVarint)?;
+
+    return Ok((Encryption { salt, nonce, memory_kib, iterations }, pos));
+}
+
+#[cfg(test)]
+mod crypt_test {
+    use super::*;
+
+    #[test]
+    fn seal_open_pt1_passphrase() {
+        // Only the right passphrase and the untouched ciphertext and aad open the payload
+
+        let encryption = Encryption::with_cost(64, 1);
+        let sealed = seal(&encryption, "hunter2", b"header", b"payload").unwrap();
+
+        assert_eq!(b"payload".len() + TAG_LEN, sealed.len());
+        assert_eq!(Ok(b"payload".to_vec()), open(&encryption, "hunter2", b"header", &sealed));
+        assert_eq!(Err(Error::DecryptionFailed), open(&encryption, "hunter3", b"header", &sealed));
+        assert_eq!(Err(Error::DecryptionFailed), open(&encryption, "hunter2", b"Header", &sealed));
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert_eq!(Err(Error::DecryptionFailed), open(&encryption, "hunter2", b"header", &tampered));
+    }
+
+    #[test]
+    fn header_pt1_roundtrip() {
+        // The salt, nonce and costs come back unchanged; fresh parameters never repeat a nonce
+
+        let encryption = Encryption::with_cost(19 * 1024, 2);
+        let header = header_bytes(&encryption);
+
+        assert_eq!(Ok((encryption, header.len())), parse_header(&header));
+        assert_ne!(encryption.nonce, Encryption::with_cost(19 * 1024, 2).nonce);
+        assert_eq!(Err(Error::TruncatedHeader { expected: 28, found: 27 }), parse_header(&header[..27]).map(|_| ()));
+    }
+
+    #[test]
+    fn open_pt1_excessive_cost() {
+        // Costs above the limit from a forged header are refused before any key is derived
+
+        let encryption = Encryption::with_cost(64, 1);
+        let sealed = seal(&encryption, "hunter2", b"header", b"payload").unwrap();
+
+        let mut header = header_bytes(&Encryption { memory_kib: u32::MAX, iterations: u32::MAX, ..encryption });
+        let (forged, _) = parse_header(&header).unwrap();
+        assert_eq!(Err(Error::ExcessiveCost { memory_kib: u32::MAX, iterations: u32::MAX }), open(&forged, "hunter2", b"header", &sealed));
+
+        header = header_bytes(&Encryption { iterations: Params::DEFAULT_T_COST * MAX_COST_FACTOR + 1, ..encryption });
+        let (forged, _) = parse_header(&header).unwrap();
+        assert_eq!(Err(Error::ExcessiveCost { memory_kib: 64, iterations: 33 }), open(&forged, "hunter2", b"header", &sealed));
+    }
+}
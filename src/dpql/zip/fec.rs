@@ -0,0 +1,333 @@
+/* Reed–Solomon forward error correction over GF(256) for the bytes of a diropqlz file.
+
+The data is cut into chunks of 255 - parity bytes and every chunk is followed by `parity` check bytes, so every
+codeword is at most 255 bytes long; the last codeword is shortened. A codeword is corrected as long as at most
+parity / 2 of its bytes are wrong. The field uses the primitive polynomial x^8 + x^4 + x^3 + x^2 + 1 and the
+generator polynomial has the roots alpha^0 .. alpha^(parity - 1). */
+
+use crate::error::{Error, Result};
+
+// Reducing polynomial of GF(256)
+const PRIMITIVE_POLY: u16 = 0x11D;
+
+// Longest Reed–Solomon codeword over GF(256)
+const CODEWORD_LEN: usize = 255;
+
+// Largest number of check bytes per codeword; a codeword needs at least one data byte
+pub const MAX_PARITY: u8 = 254;
+
+// Exponent and logarithm tables of GF(256); exp is doubled so that products need no modulo
+struct Tables {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+const TABLES: Tables = build_tables();
+
+// build_tables function fills the exponent and logarithm tables of the generator alpha = 2
+
+const fn build_tables() -> Tables {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut value: u16 = 1;
+    let mut i = 0;
+
+    while i < 255 {
+        exp[i] = value as u8;
+        exp[i + 255] = value as u8;
+        log[value as usize] = i as u8;
+
+        value <<= 1;
+        if value & 0x100 != 0 {
+            value ^= PRIMITIVE_POLY;
+        }
+
+        i += 1;
+    }
+
+    return Tables { exp, log };
+}
+
+// mul function multiplies two field elements
+
+pub(super) fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+
+    return TABLES.exp[TABLES.log[a as usize] as usize + TABLES.log[b as usize] as usize];
+}
+
+// div function divides a field element by a non-zero field element
+
+pub(super) fn div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+
+    return TABLES.exp[TABLES.log[a as usize] as usize + 255 - TABLES.log[b as usize] as usize];
+}
+
+// alpha function returns alpha raised to the power p, where p may be negative
+
+fn alpha(p: isize) -> u8 {
+    return TABLES.exp[p.rem_euclid(255) as usize];
+}
+
+// eval function evaluates a polynomial stored lowest degree first at x
+
+fn eval(poly: &[u8], x: u8) -> u8 {
+    return poly.iter().rev().fold(0, |acc, &coef| mul(acc, x) ^ coef);
+}
+
+// generator function returns the generator polynomial of the code, highest degree first
+
+fn generator(parity: usize) -> Vec<u8> {
+    let mut poly: Vec<u8> = vec![1];
+
+    for i in 0..parity {
+        // Multiply by (x - alpha^i); subtraction is addition in GF(256)
+        let root = alpha(i as isize);
+        let mut next = poly.clone();
+        next.push(0);
+
+        for (j, &coef) in poly.iter().enumerate() {
+            next[j + 1] ^= mul(coef, root);
+        }
+
+        poly = next;
+    }
+
+    return poly;
+}
+
+// encode function appends `parity` check bytes to every chunk of 255 - parity data bytes
+
+pub fn encode(data: &[u8], parity: u8) -> Result<Vec<u8>> {
+    if parity == 0 || parity > MAX_PARITY {
+        return Err(Error::InvalidRedundancy(parity));
+    }
+
+    let parity = parity as usize;
+
+    let generator = generator(parity);
+    let mut encoded: Vec<u8> = Vec::new();
+
+    for chunk in data.chunks(CODEWORD_LEN - parity) {
+        // The check bytes are the remainder of chunk * x^parity divided by the generator
+        let mut remainder = vec![0u8; parity];
+
+        for &byte in chunk {
+            let factor = byte ^ remainder[0];
+            remainder.rotate_left(1);
+            remainder[parity - 1] = 0;
+
+            for (rem, &coef) in remainder.iter_mut().zip(&generator[1..]) {
+                *rem ^= mul(coef, factor);
+            }
+        }
+
+        encoded.extend(chunk);
+        encoded.extend(remainder);
+    }
+
+    return Ok(encoded);
+}
+
+// decode function corrects every codeword written by encode and returns the data bytes
+
+pub fn decode(encoded: &[u8], parity: u8) -> Result<Vec<u8>> {
+    if parity == 0 || parity > MAX_PARITY {
+        return Err(Error::InvalidRedundancy(parity));
+    }
+
+    let parity = parity as usize;
+
+    let mut data: Vec<u8> = Vec::new();
+
+    for (index, codeword) in encoded.chunks(CODEWORD_LEN).enumerate() {
+        // A codeword without data bytes was cut short
+        if codeword.len() <= parity {
+            return Err(Error::UncorrectableCodeword { index });
+        }
+
+        let mut codeword = codeword.to_vec();
+
+        if !correct(&mut codeword, parity) {
+            return Err(Error::UncorrectableCodeword { index });
+        }
+
+        data.extend(&codeword[..codeword.len() - parity]);
+    }
+
+    return Ok(data);
+}
+
+// correct function fixes up to parity / 2 wrong bytes of a codeword in place and returns whether it succeeded
+
+fn correct(codeword: &mut [u8], parity: usize) -> bool {
+    let syndromes = syndromes(codeword, parity);
+
+    if syndromes.iter().all(|&s| s == 0) {
+        return true;
+    }
+
+    let locator = error_locator(&syndromes);
+    let error_count = locator.len() - 1;
+
+    if error_count > parity / 2 {
+        return false;
+    }
+
+    // Chien search: byte j is wrong if the locator vanishes at the inverse of its position
+    let n = codeword.len();
+    let positions: Vec<usize> = (0..n).filter(|&j| eval(&locator, alpha(-((n - 1 - j) as isize))) == 0).collect();
+
+    if positions.len() != error_count {
+        return false;
+    }
+
+    // Forney algorithm: the evaluator is the syndrome polynomial times the locator, modulo x^parity
+    let mut evaluator = vec![0u8; parity];
+    for (i, &s) in syndromes.iter().enumerate() {
+        for (j, &l) in locator.iter().enumerate().take(parity - i) {
+            evaluator[i + j] ^= mul(s, l);
+        }
+    }
+
+    // The formal derivative keeps the odd powers of the locator
+    let derivative: Vec<u8> = locator.iter().enumerate().skip(1).map(|(i, &l)| if i % 2 == 1 { l } else { 0 }).collect();
+
+    for j in positions {
+        let x = alpha((n - 1 - j) as isize);
+        let x_inv = div(1, x);
+        let denominator = eval(&derivative, x_inv);
+
+        if denominator == 0 {
+            return false;
+        }
+
+        codeword[j] ^= mul(x, div(eval(&evaluator, x_inv), denominator));
+    }
+
+    // Too many errors can look like a correctable pattern; the corrected codeword must be valid
+    return is_codeword(codeword, parity);
+}
+
+// syndromes function evaluates the codeword at alpha^0 .. alpha^(parity - 1)
+
+fn syndromes(codeword: &[u8], parity: usize) -> Vec<u8> {
+    return (0..parity)
+        .map(|i| {
+            let x = alpha(i as isize);
+            codeword.iter().fold(0, |acc, &byte| mul(acc, x) ^ byte)
+        })
+        .collect();
+}
+
+// is_codeword function returns whether every syndrome of the codeword is zero
+
+fn is_codeword(codeword: &[u8], parity: usize) -> bool {
+    return syndromes(codeword, parity).iter().all(|&s| s == 0);
+}
+
+// error_locator function runs Berlekamp–Massey on the syndromes and returns the error locator polynomial,
+// lowest degree first and without trailing zero coefficients
+
+fn error_locator(syndromes: &[u8]) -> Vec<u8> {
+    let mut locator: Vec<u8> = vec![1];
+    let mut previous: Vec<u8> = vec![1];
+    let mut errors: usize = 0;
+    let mut shift: usize = 1;
+    let mut previous_discrepancy: u8 = 1;
+
+    for n in 0..syndromes.len() {
+        let mut discrepancy = syndromes[n];
+        for i in 1..=errors.min(locator.len() - 1) {
+            discrepancy ^= mul(locator[i], syndromes[n - i]);
+        }
+
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        // locator -= discrepancy / previous_discrepancy * x^shift * previous
+        let factor = div(discrepancy, previous_discrepancy);
+        let updated_len = locator.len().max(previous.len() + shift);
+        let mut updated = locator.clone();
+        updated.resize(updated_len, 0);
+
+        for (i, &coef) in previous.iter().enumerate() {
+            updated[i + shift] ^= mul(factor, coef);
+        }
+
+        if 2 * errors <= n {
+            errors = n + 1 - errors;
+            previous = locator;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        }
+
+        else {
+            shift += 1;
+        }
+
+        locator = updated;
+    }
+
+    while locator.len() > 1 && locator[locator.len() - 1] == 0 {
+        locator.pop();
+    }
+
+    return locator;
+}
+
+#[cfg(test)]
+mod fec_test {
+    use super::*;
+
+    #[test]
+    fn encode_decode_pt1_clean() {
+        // Data spanning several codewords comes back unchanged; every codeword carries the parity bytes
+
+        let data: Vec<u8> = (0..600u32).map(|i| (i * 31 % 256) as u8).collect();
+        let encoded = encode(&data, 10).unwrap();
+
+        assert_eq!(600 + 3 * 10, encoded.len());
+        assert_eq!(data, decode(&encoded, 10).unwrap());
+        assert_eq!(Vec::<u8>::new(), decode(&encode(&[], 4).unwrap(), 4).unwrap());
+    }
+
+    #[test]
+    fn encode_decode_pt2_errors() {
+        // Up to parity / 2 wrong bytes per codeword are corrected, including in the shortened last codeword
+
+        let data: Vec<u8> = (0..400u32).map(|i| (i * 7 % 256) as u8).collect();
+        let mut encoded = encode(&data, 8).unwrap();
+
+        for pos in [0, 17, 100, 254, 255, 300, 401, 407] {
+            encoded[pos] ^= 0x5A;
+        }
+
+        assert_eq!(data, decode(&encoded, 8).unwrap());
+    }
+
+    #[test]
+    fn decode_pt1_uncorrectable() {
+        // More wrong bytes than the parity can fix, or a codeword that lost its data, are rejected
+
+        let data = b"Hello, World!".to_vec();
+        let mut encoded = encode(&data, 4).unwrap();
+
+        for pos in [0, 1, 2] {
+            encoded[pos] ^= 0xFF;
+        }
+
+        assert_eq!(Err(Error::UncorrectableCodeword { index: 0 }), decode(&encoded, 4));
+        assert_eq!(Err(Error::UncorrectableCodeword { index: 0 }), decode(&encode(&data, 4).unwrap()[..4], 4));
+        assert_eq!(Err(Error::InvalidRedundancy(0)), decode(&data, 0));
+        assert_eq!(Err(Error::InvalidRedundancy(255)), decode(&data, 255));
+        assert_eq!(Err(Error::InvalidRedundancy(255)), encode(&data, 255));
+    }
+}
@@ -0,0 +1,550 @@
+/* Public-key recipients and sender signatures of diropqlz files, layered around write_meta and read_meta.
+
+A file for recipients sets the FLAG_RECIPIENTS feature flag. Its payload is sealed with ChaCha20-Poly1305 under a
+random message key and, after the other header fields, the header stores
+    nonce (12 bytes) | stanza count (varint) | per stanza: ephemeral X25519 key (32 bytes) | wrapped key (48 bytes)
+Every stanza wraps the message key for one recipient under HKDF-SHA256 of the X25519 shared secret of a fresh
+ephemeral key and the recipient's key, like the X25519 recipients of age.
+
+A signed file sets FLAG_SIGNED and its header ends with
+    Ed25519 public key (32 bytes) | signature (64 bytes)
+The signature covers the format version, the feature flags, the Reed–Solomon parity, every other header field and
+the payload, as the bytes stored in the file rather than as this build would write them again. */
+
+use super::{associated_data, check_parity, compress, crypt, decode_base85, expand, header_bytes, meta_flags, prepare_sealed, random_bytes, read_container, split_meta, varint, write_meta};
+use super::{DpqlzMeta, Format, FORMAT_VERSION};
+use crate::error::{Error, Result};
+
+use base85::encode;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::StaticSecret;
+
+// Size in bytes of X25519 and Ed25519 keys and of the message key
+pub const KEY_LEN: usize = 32;
+
+// Size in bytes of a message key wrapped for one recipient, including its tag
+pub const WRAPPED_LEN: usize = KEY_LEN + crypt::TAG_LEN;
+
+// Size in bytes of an Ed25519 signature
+pub const SIGNATURE_LEN: usize = 64;
+
+// Prefixes of the text form of keys; ':' is not part of the Base85 alphabet
+const SECRET_PREFIX: &str = "dpqlz-secret:";
+const PUBLIC_PREFIX: &str = "dpqlz-public:";
+
+// HKDF info string that separates stanza wrapping keys from any other use of the shared secret
+const WRAP_INFO: &[u8] = b"diropqlz x25519 stanza";
+
+// Message key wrapped for one recipient
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stanza {
+    pub ephemeral: [u8; KEY_LEN],
+    pub wrapped_key: [u8; WRAPPED_LEN],
+}
+
+// Payload nonce and one stanza per recipient
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recipients {
+    pub nonce: [u8; crypt::NONCE_LEN],
+    pub stanzas: Vec<Stanza>,
+}
+
+// Sender's Ed25519 public key and signature
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signature {
+    pub public_key: [u8; KEY_LEN],
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+// Secret half of a keypair: an X25519 key to open messages and an Ed25519 key to sign them
+
+#[derive(Clone)]
+pub struct SecretKey {
+    x25519: StaticSecret,
+    ed25519: SigningKey,
+}
+
+// Public half of a keypair: an X25519 key to encrypt to and an Ed25519 key to verify signatures with
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PublicKey {
+    x25519: [u8; KEY_LEN],
+    ed25519: [u8; KEY_LEN],
+}
+
+impl SecretKey {
+    // generate function creates a new random keypair
+
+    pub fn generate() -> Self {
+        return SecretKey::from_bytes(random_bytes(), random_bytes());
+    }
+
+    // from_bytes function builds the keypair from its X25519 secret and Ed25519 seed
+
+    fn from_bytes(x25519: [u8; KEY_LEN], ed25519: [u8; KEY_LEN]) -> Self {
+        return SecretKey { x25519: StaticSecret::from(x25519), ed25519: SigningKey::from_bytes(&ed25519) };
+    }
+
+    // public_key function returns the public half of the keypair
+
+    pub fn public_key(&self) -> PublicKey {
+        return PublicKey {
+            x25519: x25519_dalek::PublicKey::from(&self.x25519).to_bytes(),
+            ed25519: self.ed25519.verifying_key().to_bytes(),
+        };
+    }
+
+    // to_text function returns the key as a single line of text
+
+    pub fn to_text(&self) -> String {
+        let mut bytes = self.x25519.to_bytes().to_vec();
+        bytes.extend(self.ed25519.to_bytes());
+
+        return format!("{}{}", SECRET_PREFIX, encode(&bytes));
+    }
+
+    // from_text function reads a key written by to_text; blank lines and lines starting with # are skipped
+
+    pub fn from_text(text: &str) -> Result<Self> {
+        let (x25519, ed25519) = parse_key_text(text, SECRET_PREFIX)?;
+
+        return Ok(SecretKey::from_bytes(x25519, ed25519));
+    }
+}
+
+impl PublicKey {
+    // to_text function returns the key as a single line of text
+
+    pub fn to_text(&self) -> String {
+        let mut bytes = self.x25519.to_vec();
+        bytes.extend(self.ed25519);
+
+        return format!("{}{}", PUBLIC_PREFIX, encode(&bytes));
+    }
+
+    // from_text function reads a key written by to_text and rejects keys that cannot be encrypted to or verified with
+
+    pub fn from_text(text: &str) -> Result<Self> {
+        let (x25519, ed25519) = parse_key_text(text, PUBLIC_PREFIX)?;
+
+        // A low-order X25519 point gives a shared secret anyone can compute
+        let probe = StaticSecret::from(random_bytes::<KEY_LEN>());
+        if !probe.diffie_hellman(&x25519_dalek::PublicKey::from(x25519)).was_contributory() {
+            return Err(Error::InvalidKey);
+        }
+
+        VerifyingKey::from_bytes(&ed25519).map_err(|_| Error::InvalidKey)?;
+
+        return Ok(PublicKey { x25519, ed25519 });
+    }
+}
+
+/* write_bytes function converts binary data to a diropqlz file encrypted to every recipient and signed by the
+sender, with `parity` Reed–Solomon check bytes per 255-byte codeword; without recipients the payload is left
+unencrypted and without a sender the file is unsigned. A parity of 255 is rejected */
+
+pub fn write_bytes(data: &[u8], recipients: &[PublicKey], sender: Option<&SecretKey>, parity: u8) -> Result<String> {
+    check_parity(parity)?;
+
+    let (mut meta_data, mut payload) = compress(data)?;
+    meta_data.fec_parity = parity;
+
+    if !recipients.is_empty() {
+        let message_key: [u8; KEY_LEN] = random_bytes();
+        let nonce: [u8; crypt::NONCE_LEN] = random_bytes();
+
+        prepare_sealed(&mut meta_data, payload.len());
+        meta_data.recipients = Some(Recipients {
+            nonce,
+            stanzas: recipients.iter().map(|recipient| wrap(&message_key, recipient)).collect(),
+        });
+
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&message_key));
+        payload = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &payload, aad: &associated_data(&meta_data) })
+            .expect("chacha20poly1305 encrypts payloads of any size a diropqlz file can hold");
+    }
+
+    if let Some(sender) = sender {
+        sign(&mut meta_data, &payload, sender);
+    }
+
+    return Ok(write_meta(&meta_data, &payload));
+}
+
+/* read_bytes function opens a diropqlz file encrypted to the secret key and returns the original binary data. A
+signature, if there is one, must be valid, but read_bytes does not authenticate the sender: anyone can strip the
+signature and FLAG_SIGNED from a file, which then reads as unsigned. Call verify with the expected sender first */
+
+pub fn read_bytes(prog: &str, secret: &SecretKey) -> Result<Vec<u8>> {
+    let (meta_data, sealed, signed) = read_signed(prog)?;
+    check_signature(&meta_data, &signed)?;
+
+    let recipients = match &meta_data.recipients {
+        Some(recipients) => recipients,
+        None => return Err(Error::EncryptionMismatch { encrypted: false }),
+    };
+
+    let message_key = match recipients.stanzas.iter().find_map(|stanza| unwrap(stanza, secret)) {
+        Some(message_key) => message_key,
+        None => return Err(Error::NotARecipient),
+    };
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&message_key));
+    let payload = cipher
+        .decrypt(Nonce::from_slice(&recipients.nonce), Payload { msg: &sealed, aad: &associated_data(&meta_data) })
+        .map_err(|_| Error::DecryptionFailed)?;
+
+    return expand(&meta_data, &payload);
+}
+
+// verify function checks that the diropqlz file is signed by the sender and unchanged since it was signed; a file
+// without a signature fails with MissingSignature
+
+pub fn verify(prog: &str, sender: &PublicKey) -> Result<()> {
+    let (meta_data, _, signed) = read_signed(prog)?;
+
+    match check_signature(&meta_data, &signed)? {
+        Some(public_key) if public_key == sender.ed25519 => return Ok(()),
+        Some(_) => return Err(Error::InvalidSignature),
+        None => return Err(Error::MissingSignature),
+    }
+}
+
+// sign function stores the sender's public key and signature in the metadata
+
+fn sign(meta: &mut DpqlzMeta, payload: &[u8], sender: &SecretKey) {
+    let public_key = sender.ed25519.verifying_key().to_bytes();
+    meta.signature = Some(Signature { public_key, signature: [0; SIGNATURE_LEN] });
+
+    // write_meta stores exactly these bytes, so the reader sees the same ones
+    let flags = meta_flags(meta);
+    let header = header_bytes(meta, flags);
+    let format = Format { version: FORMAT_VERSION, flags, parity: meta.fec_parity };
+
+    let signature = sender.ed25519.sign(&signed_bytes(format, &header[..header.len() - SIGNATURE_LEN], payload)).to_bytes();
+    meta.signature = Some(Signature { public_key, signature });
+}
+
+// read_signed function reads a single-block diropqlz file like read_meta and also returns the bytes its signature
+// covers, exactly as they are stored in the file
+
+fn read_signed(prog: &str) -> Result<(DpqlzMeta, Vec<u8>, Vec<u8>)> {
+    let (format, decoded_msg) = read_container(prog)?;
+    let (meta_data, payload) = split_meta(format, &decoded_msg)?;
+
+    // The header is everything before the payload; only its trailing signature value is left out
+    let header_len = decoded_msg.len() - payload.len();
+    let signed_len = if meta_data.signature.is_some() { header_len - SIGNATURE_LEN } else { header_len };
+    let signed = signed_bytes(format, &decoded_msg[..signed_len], &payload);
+
+    return Ok((meta_data, payload, signed));
+}
+
+// check_signature function verifies the signature over the signed bytes against the public key stored next to it
+// and returns that key, or None for an unsigned file
+
+fn check_signature(meta: &DpqlzMeta, signed: &[u8]) -> Result<Option<[u8; KEY_LEN]>> {
+    let signature = match meta.signature {
+        Some(signature) => signature,
+        None => return Ok(None),
+    };
+
+    let verifying_key = VerifyingKey::from_bytes(&signature.public_key).map_err(|_| Error::InvalidSignature)?;
+
+    verifying_key
+        .verify_strict(signed, &ed25519_dalek::Signature::from_bytes(&signature.signature))
+        .map_err(|_| Error::InvalidSignature)?;
+
+    return Ok(Some(signature.public_key));
+}
+
+// signed_bytes function returns the bytes a signature covers: the format version, the flags, the Reed–Solomon
+// parity, the header up to the signature value and the payload
+
+fn signed_bytes(format: Format, header: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![format.version, format.flags, format.parity];
+    bytes.extend(header);
+    bytes.extend(payload);
+
+    return bytes;
+}
+
+// wrap function encrypts the message key for one recipient under a fresh ephemeral key
+
+fn wrap(message_key: &[u8; KEY_LEN], recipient: &PublicKey) -> Stanza {
+    let ephemeral = StaticSecret::from(random_bytes::<KEY_LEN>());
+    let ephemeral_public = x25519_dalek::PublicKey::from(&ephemeral).to_bytes();
+    let shared = ephemeral.diffie_hellman(&x25519_dalek::PublicKey::from(recipient.x25519));
+
+    let cipher = ChaCha20Poly1305::new(&wrap_key(shared.as_bytes(), &ephemeral_public, &recipient.x25519));
+    let wrapped = cipher
+        .encrypt(&Nonce::default(), message_key.as_slice())
+        .expect("chacha20poly1305 encrypts a 32-byte key");
+
+    return Stanza {
+        ephemeral: ephemeral_public,
+        wrapped_key: wrapped.try_into().expect("a wrapped key is the key followed by its tag"),
+    };
+}
+
+// unwrap function returns the message key if the stanza was made for the secret key
+
+fn unwrap(stanza: &Stanza, secret: &SecretKey) -> Option<[u8; KEY_LEN]> {
+    let shared = secret.x25519.diffie_hellman(&x25519_dalek::PublicKey::from(stanza.ephemeral));
+
+    if !shared.was_contributory() {
+        return None;
+    }
+
+    let own_public = x25519_dalek::PublicKey::from(&secret.x25519).to_bytes();
+    let cipher = ChaCha20Poly1305::new(&wrap_key(shared.as_bytes(), &stanza.ephemeral, &own_public));
+    let message_key = cipher.decrypt(&Nonce::default(), stanza.wrapped_key.as_slice()).ok()?;
+
+    return message_key.try_into().ok();
+}
+
+// wrap_key function derives the key of one stanza from the shared secret and both public keys; every stanza has
+// its own ephemeral key, so the fixed zero nonce is never reused with the same key
+
+fn wrap_key(shared: &[u8; KEY_LEN], ephemeral: &[u8; KEY_LEN], recipient: &[u8; KEY_LEN]) -> Key {
+    let mut salt = ephemeral.to_vec();
+    salt.extend(recipient);
+
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, &mut key)
+        .expect("32 bytes is a valid hkdf-sha256 output length");
+
+    return key;
+}
+
+// recipients_bytes function converts the recipient stanzas to the bytes stored in the header
+
+pub(super) fn recipients_bytes(recipients: &Recipients) -> Vec<u8> {
+    let mut header: Vec<u8> = recipients.nonce.to_vec();
+    varint::write_u64(recipients.stanzas.len() as u64, &mut header);
+
+    for stanza in &recipients.stanzas {
+        header.extend(stanza.ephemeral);
+        header.extend(stanza.wrapped_key);
+    }
+
+    return header;
+}
+
+// parse_recipients function reads the stanzas written by recipients_bytes and returns them with their length in bytes
+
+pub(super) fn parse_recipients(header: &[u8]) -> Result<(Recipients, usize)> {
+    if header.len() < crypt::NONCE_LEN {
+        return Err(Error::TruncatedHeader { expected: crypt::NONCE_LEN, found: header.len() });
+    }
+
+    let mut nonce = [0u8; crypt::NONCE_LEN];
+    nonce.copy_from_slice(&header[..crypt::NONCE_LEN]);
+
+    let mut pos = crypt::NONCE_LEN;
+    let stanza_count = varint::read_u64(header, &mut pos)?;
+    let mut stanzas: Vec<Stanza> = Vec::new();
+
+    for _ in 0..stanza_count {
+        if header.len() - pos < KEY_LEN + WRAPPED_LEN {
+            return Err(Error::TruncatedHeader { expected: pos + KEY_LEN + WRAPPED_LEN, found: header.len() });
+        }
+
+        let mut ephemeral = [0u8; KEY_LEN];
+        ephemeral.copy_from_slice(&header[pos..pos + KEY_LEN]);
+
+        let mut wrapped_key = [0u8; WRAPPED_LEN];
+        wrapped_key.copy_from_slice(&header[pos + KEY_LEN..pos + KEY_LEN + WRAPPED_LEN]);
+
+        stanzas.push(Stanza { ephemeral, wrapped_key });
+        pos += KEY_LEN + WRAPPED_LEN;
+    }
+
+    return Ok((Recipients { nonce, stanzas }, pos));
+}
+
+// signature_bytes function converts the signature to the bytes stored at the end of the header
+
+pub(super) fn signature_bytes(signature: &Signature) -> Vec<u8> {
+    let mut header: Vec<u8> = signature.public_key.to_vec();
+    header.extend(signature.signature);
+
+    return header;
+}
+
+// parse_signature function reads the signature written by signature_bytes and returns it with its length in bytes
+
+pub(super) fn parse_signature(header: &[u8]) -> Result<(Signature, usize)> {
+    if header.len() < KEY_LEN + SIGNATURE_LEN {
+        return Err(Error::TruncatedHeader { expected: KEY_LEN + SIGNATURE_LEN, found: header.len() });
+    }
+
+    let mut public_key = [0u8; KEY_LEN];
+    public_key.copy_from_slice(&header[..KEY_LEN]);
+
+    let mut signature = [0u8; SIGNATURE_LEN];
+    signature.copy_from_slice(&header[KEY_LEN..KEY_LEN + SIGNATURE_LEN]);
+
+    return Ok((Signature { public_key, signature }, KEY_LEN + SIGNATURE_LEN));
+}
+
+// parse_key_text function returns the two 32-byte halves of the first key line with the given prefix
+
+fn parse_key_text(text: &str, prefix: &str) -> Result<([u8; KEY_LEN], [u8; KEY_LEN])> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let encoded = match line.and_then(|line| line.strip_prefix(prefix)) {
+        Some(encoded) => encoded,
+        None => return Err(Error::InvalidKey),
+    };
+
+    let bytes = decode_base85(encoded).map_err(|_| Error::InvalidKey)?;
+
+    if bytes.len() != 2 * KEY_LEN {
+        return Err(Error::InvalidKey);
+    }
+
+    let mut first = [0u8; KEY_LEN];
+    first.copy_from_slice(&bytes[..KEY_LEN]);
+
+    let mut second = [0u8; KEY_LEN];
+    second.copy_from_slice(&bytes[KEY_LEN..]);
+
+    return Ok((first, second));
+}
+
+#[cfg(test)]
+mod keys_test {
+    use super::*;
+    use super::super::{read_meta, write_container, FLAG_CODEBOOK, FLAG_SIGNED};
+
+    #[test]
+    fn write_read_pt1_recipients() {
+        // Every recipient opens the message; anyone else is not a recipient
+
+        let alice = SecretKey::generate();
+        let bob = SecretKey::generate();
+        let eve = SecretKey::generate();
+
+        let encoded = write_bytes(b"meet at noon", &[alice.public_key(), bob.public_key()], None, 0).unwrap();
+
+        assert_eq!(Ok(b"meet at noon".to_vec()), read_bytes(&encoded, &alice));
+        assert_eq!(Ok(b"meet at noon".to_vec()), read_bytes(&encoded, &bob));
+        assert_eq!(Err(Error::NotARecipient), read_bytes(&encoded, &eve));
+        assert_eq!(Err(Error::EncryptionMismatch { encrypted: true }), super::super::read_bytes(&encoded));
+        assert_eq!(Err(Error::MissingSignature), verify(&encoded, &alice.public_key()));
+    }
+
+    #[test]
+    fn verify_pt1_sender() {
+        // A signature proves the sender and that nothing changed since signing; it also covers the parity
+
+        let alice = SecretKey::generate();
+        let bob = SecretKey::generate();
+
+        let encoded = write_bytes(b"signed, sealed, delivered", &[bob.public_key()], Some(&alice), 8).unwrap();
+
+        assert_eq!(Ok(()), verify(&encoded, &alice.public_key()));
+        assert_eq!(Err(Error::InvalidSignature), verify(&encoded, &bob.public_key()));
+        assert_eq!(Ok(b"signed, sealed, delivered".to_vec()), read_bytes(&encoded, &bob));
+
+        // A changed header field breaks the signature
+        let (mut meta, payload) = read_meta(&encoded).unwrap();
+        meta.moffset ^= 1;
+        let tampered = write_meta(&meta, &payload);
+
+        assert_eq!(Err(Error::InvalidSignature), verify(&tampered, &alice.public_key()));
+        assert_eq!(Err(Error::InvalidSignature), read_bytes(&tampered, &bob));
+
+        // So does a changed parity, even though the file is still readable
+        let (mut meta, payload) = read_meta(&encoded).unwrap();
+        meta.fec_parity = 16;
+        let reprotected = write_meta(&meta, &payload);
+
+        assert_eq!(Err(Error::InvalidSignature), verify(&reprotected, &alice.public_key()));
+
+        // Signed files without recipients are still readable without a key
+        let plain = write_bytes(b"public notice", &[], Some(&alice), 0).unwrap();
+
+        assert_eq!(Ok(()), verify(&plain, &alice.public_key()));
+        assert_eq!(Ok(b"public notice".to_vec()), super::super::read_bytes(&plain));
+        assert_eq!(Err(Error::InvalidRedundancy(255)), write_bytes(b"public notice", &[], Some(&alice), 255));
+    }
+
+    #[test]
+    fn verify_pt2_stripped() {
+        // A file whose signature was cut off still opens, but no longer verifies as coming from the sender
+
+        let alice = SecretKey::generate();
+        let bob = SecretKey::generate();
+        let encoded = write_bytes(b"from alice", &[bob.public_key()], Some(&alice), 0).unwrap();
+
+        let (format, msg) = read_container(&encoded).unwrap();
+        let (_, payload) = read_meta(&encoded).unwrap();
+        let signature_start = msg.len() - payload.len() - KEY_LEN - SIGNATURE_LEN;
+
+        let mut stripped_msg = msg[..signature_start].to_vec();
+        stripped_msg.extend(&payload);
+        let stripped = write_container(format.flags & !FLAG_SIGNED, &stripped_msg);
+
+        assert_eq!(Ok(b"from alice".to_vec()), read_bytes(&stripped, &bob));
+        assert_eq!(Err(Error::MissingSignature), verify(&stripped, &alice.public_key()));
+    }
+
+    #[test]
+    fn verify_pt3_stored_bytes() {
+        // The signature covers the bytes stored in the file rather than the header this build would write
+
+        let alice = SecretKey::generate();
+        let encoded = write_bytes(b"public notice", &[], Some(&alice), 0).unwrap();
+
+        // A second encoding of the leading mlen varint is not accepted
+        let (format, mut msg) = read_container(&encoded).unwrap();
+        assert!(msg[0] < 0x80);
+        msg.splice(0..1, [msg[0] | 0x80, 0]);
+
+        assert_eq!(Err(Error::InvalidVarint), verify(&write_container(format.flags, &msg), &alice.public_key()));
+
+        // Another writer may use the codebook header for short codewords; its signature still verifies
+        let (meta, payload) = read_meta(&encoded).unwrap();
+        let flags = format.flags | FLAG_CODEBOOK;
+        let mut header = header_bytes(&meta, flags);
+        let signed_len = header.len() - SIGNATURE_LEN;
+
+        let signature = alice.ed25519.sign(&signed_bytes(Format { flags, ..format }, &header[..signed_len], &payload));
+        header[signed_len..].copy_from_slice(&signature.to_bytes());
+        header.extend(&payload);
+
+        let rewritten = write_container(flags, &header);
+        assert_eq!(Ok(()), verify(&rewritten, &alice.public_key()));
+        assert_eq!(Ok(b"public notice".to_vec()), super::super::read_bytes(&rewritten));
+    }
+
+    #[test]
+    fn key_text_pt1_roundtrip() {
+        // Keys survive their text form; comments are skipped and other text is rejected
+
+        let secret = SecretKey::generate();
+        let public = secret.public_key();
+        let file = format!("# public key: {}\n{}\n", public.to_text(), secret.to_text());
+
+        assert_eq!(public, SecretKey::from_text(&file).unwrap().public_key());
+        assert_eq!(Ok(public), PublicKey::from_text(&public.to_text()));
+        assert_eq!(Err(Error::InvalidKey), PublicKey::from_text(&secret.to_text()).map(|_| ()));
+        assert_eq!(Err(Error::InvalidKey), SecretKey::from_text("dpqlz-secret:0000").map(|_| ()));
+
+        // The all-zero X25519 point would let anyone compute the shared secret
+        let mut weak = vec![0u8; KEY_LEN];
+        weak.extend(public.ed25519);
+        assert_eq!(Err(Error::InvalidKey), PublicKey::from_text(&format!("{}{}", PUBLIC_PREFIX, encode(&weak))));
+    }
+}
@@ -0,0 +1,241 @@
+/* Shamir secret sharing of a diropqlz file: the message is written as a diropqlz file and the decoded bytes of
+that file are split into shares so that any threshold of them recover it and fewer reveal nothing about it.
+
+Every byte of the file is the constant term of a random polynomial of degree threshold - 1 over GF(256), and share
+x stores the values of these polynomials at x. A share is itself a diropqlz string that sets the FLAG_SHARE feature
+flag; after the format version and flags it stores
+    share index (1 byte, 1 to 255) | threshold (1 byte) | set id (8 bytes) | CRC32 of the share bytes (4 bytes) |
+    share bytes
+The random set id ties together the shares of one split. All shares are as long as the file they recover. */
+
+use super::fec::{div, mul};
+use super::{decode_base85, fill_random, random_bytes, read_container, strip_magic, write_container, FLAG_SHARE, FORMAT_MARKER};
+use crate::error::{Error, Result};
+
+use base85::encode;
+
+// Size in bytes of the random id shared by the shares of one split
+pub const SET_ID_LEN: usize = 8;
+
+// Size in bytes of the share header: index, threshold, set id and checksum
+const SHARE_HEADER_LEN: usize = 2 + SET_ID_LEN + 4;
+
+// One share of a split diropqlz file
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Share {
+    pub index: u8,     // x coordinate of the share; never 0, which is where the file is stored
+    pub threshold: u8,     // Number of shares needed to recover the file
+    pub set_id: [u8; SET_ID_LEN],
+    pub bytes: Vec<u8>,     // Values of the polynomials at index, one per byte of the file
+}
+
+// split function converts a text message to `shares` diropqlz shares, any `threshold` of which recover it
+
+pub fn split(text: &str, threshold: u8, shares: u8) -> Result<Vec<String>> {
+    return split_bytes(text.as_bytes(), threshold, shares);
+}
+
+// split_bytes function converts binary data to `shares` diropqlz shares, any `threshold` of which recover it
+
+pub fn split_bytes(data: &[u8], threshold: u8, shares: u8) -> Result<Vec<String>> {
+    if threshold == 0 || threshold > shares {
+        return Err(Error::InvalidThreshold { threshold, shares });
+    }
+
+    // The secret is the decoded diropqlz file, so combining hands zip::read a complete file
+    let prog = super::write_bytes(data);
+    let secret = decode_base85(&strip_magic(&prog)?[FORMAT_MARKER.len_utf8()..])?;
+
+    let set_id: [u8; SET_ID_LEN] = random_bytes();
+    let mut coefficients = vec![0u8; secret.len() * (threshold as usize - 1)];
+    fill_random(&mut coefficients);
+
+    let mut encoded: Vec<String> = Vec::new();
+
+    for index in 1..=shares {
+        let bytes: Vec<u8> = secret.iter().enumerate()
+            .map(|(i, &byte)| {
+                // Horner's rule over the random coefficients of byte i, then the constant term
+                let terms = &coefficients[i * (threshold as usize - 1)..(i + 1) * (threshold as usize - 1)];
+                let higher = terms.iter().rev().fold(0, |acc, &coef| mul(acc, index) ^ coef);
+
+                mul(higher, index) ^ byte
+            })
+            .collect();
+
+        encoded.push(write_share(&Share { index, threshold, set_id, bytes }));
+    }
+
+    return Ok(encoded);
+}
+
+// combine function recovers the text message from at least threshold shares of one split
+
+pub fn combine(shares: &[&str]) -> Result<String> {
+    return super::read(&recover(shares)?);
+}
+
+// combine_bytes function recovers the binary data from at least threshold shares of one split
+
+pub fn combine_bytes(shares: &[&str]) -> Result<Vec<u8>> {
+    return super::read_bytes(&recover(shares)?);
+}
+
+// recover function validates the shares and interpolates the diropqlz file they were split from
+
+fn recover(shares: &[&str]) -> Result<String> {
+    let shares: Vec<Share> = shares.iter().map(|share| read_share(share)).collect::<Result<_>>()?;
+
+    let first = match shares.first() {
+        Some(first) => first,
+        None => return Err(Error::NotEnoughShares { threshold: 0, found: 0 }),
+    };
+
+    // Every share must come from the same split and appear once
+    for (i, share) in shares.iter().enumerate() {
+        if share.set_id != first.set_id || share.threshold != first.threshold || share.bytes.len() != first.bytes.len() {
+            return Err(Error::InconsistentShares);
+        }
+
+        if shares[..i].iter().any(|other| other.index == share.index) {
+            return Err(Error::DuplicateShare(share.index));
+        }
+    }
+
+    let threshold = first.threshold as usize;
+
+    if shares.len() < threshold {
+        return Err(Error::NotEnoughShares { threshold: first.threshold, found: shares.len() });
+    }
+
+    // Lagrange interpolation at x = 0; subtraction is addition in GF(256)
+    let shares = &shares[..threshold];
+    let weights: Vec<u8> = shares.iter()
+        .map(|share| {
+            shares.iter()
+                .filter(|other| other.index != share.index)
+                .fold(1, |acc, other| mul(acc, div(other.index, other.index ^ share.index)))
+        })
+        .collect();
+
+    let secret: Vec<u8> = (0..first.bytes.len())
+        .map(|i| shares.iter().zip(&weights).fold(0, |acc, (share, &weight)| acc ^ mul(share.bytes[i], weight)))
+        .collect();
+
+    let mut prog = String::from("DIROPQLZ");
+    prog.push(FORMAT_MARKER);
+    prog.push_str(&encode(&secret));
+
+    return Ok(prog);
+}
+
+// write_share function converts a share to a diropqlz string with the FLAG_SHARE feature flag
+
+pub fn write_share(share: &Share) -> String {
+    let mut msg: Vec<u8> = vec![share.index, share.threshold];
+    msg.extend(share.set_id);
+    msg.extend(crc32fast::hash(&share.bytes).to_be_bytes());
+    msg.extend(&share.bytes);
+
+    return write_container(FLAG_SHARE, &msg);
+}
+
+// read_share function reverses write_share; files that are not shares and corrupted shares are rejected
+
+pub fn read_share(prog: &str) -> Result<Share> {
+    let (format, decoded_msg) = read_container(prog)?;
+
+    if format.flags & FLAG_SHARE == 0 {
+        return Err(Error::ShareMismatch { share: false });
+    }
+
+    if decoded_msg.len() < SHARE_HEADER_LEN {
+        return Err(Error::TruncatedHeader { expected: SHARE_HEADER_LEN, found: decoded_msg.len() });
+    }
+
+    let (index, threshold) = (decoded_msg[0], decoded_msg[1]);
+
+    if index == 0 || threshold == 0 {
+        return Err(Error::InvalidShare);
+    }
+
+    let set_id: [u8; SET_ID_LEN] = decoded_msg[2..2 + SET_ID_LEN].try_into().expect("slice is SET_ID_LEN bytes");
+    let checksum = u32::from_be_bytes(decoded_msg[2 + SET_ID_LEN..SHARE_HEADER_LEN].try_into().expect("slice is 4 bytes"));
+    let bytes = decoded_msg[SHARE_HEADER_LEN..].to_vec();
+
+    if crc32fast::hash(&bytes) != checksum {
+        return Err(Error::ChecksumMismatch { payload: true });
+    }
+
+    return Ok(Share { index, threshold, set_id, bytes });
+}
+
+#[cfg(test)]
+mod shamir_test {
+    use super::*;
+
+    #[test]
+    fn split_combine_pt1_threshold() {
+        // Any threshold of the shares, in any order, recover the message; extra shares do no harm
+
+        let shares = split("the vault code is 0451", 3, 5).unwrap();
+        let shares: Vec<&str> = shares.iter().map(String::as_str).collect();
+
+        assert_eq!(5, shares.len());
+        assert!(shares.iter().all(|share| share.starts_with("DIROPQLZ.")));
+        assert_eq!(Ok(String::from("the vault code is 0451")), combine(&shares[..3]));
+        assert_eq!(Ok(String::from("the vault code is 0451")), combine(&[shares[4], shares[1], shares[2]]));
+        assert_eq!(Ok(String::from("the vault code is 0451")), combine(&shares));
+        assert_eq!(Err(Error::NotEnoughShares { threshold: 3, found: 2 }), combine(&shares[3..]));
+    }
+
+    #[test]
+    fn split_combine_pt2_single() {
+        // A 1-of-1 and an n-of-n split of binary data both work; an impossible threshold is rejected
+
+        let data: Vec<u8> = (0..=255).collect();
+        let single = split_bytes(&data, 1, 1).unwrap();
+        let all = split_bytes(&data, 4, 4).unwrap();
+
+        assert_eq!(Ok(data.clone()), combine_bytes(&[single[0].as_str()]));
+        assert_eq!(Ok(data), combine_bytes(&all.iter().map(String::as_str).collect::<Vec<_>>()));
+        assert_eq!(Err(Error::InvalidThreshold { threshold: 3, shares: 2 }), split_bytes(b"x", 3, 2).map(|_| ()));
+        assert_eq!(Err(Error::InvalidThreshold { threshold: 0, shares: 2 }), split_bytes(b"x", 0, 2).map(|_| ()));
+    }
+
+    #[test]
+    fn combine_pt1_invalid() {
+        // Shares of other splits, repeated shares, corrupted shares and plain files are rejected
+
+        let first = split("first secret", 2, 3).unwrap();
+        let second = split("first secret", 2, 3).unwrap();
+
+        assert_eq!(Err(Error::InconsistentShares), combine(&[&first[0], &second[1]]));
+        assert_eq!(Err(Error::DuplicateShare(1)), combine(&[&first[0], &first[0]]));
+        assert_eq!(Err(Error::ShareMismatch { share: false }), combine(&[&super::super::write("first secret")]));
+        assert_eq!(Err(Error::ShareMismatch { share: true }), super::super::read(&first[0]));
+        assert_eq!(Err(Error::NotEnoughShares { threshold: 0, found: 0 }), combine(&[]));
+
+        let (_, mut msg) = read_container(&first[0]).unwrap();
+        let last = msg.len() - 1;
+        msg[last] ^= 1;
+        assert_eq!(Err(Error::ChecksumMismatch { payload: true }), read_share(&write_container(FLAG_SHARE, &msg)).map(|_| ()));
+
+        let mut share = read_share(&first[0]).unwrap();
+        share.index = 0;
+        assert_eq!(Err(Error::InvalidShare), read_share(&write_share(&share)).map(|_| ()));
+    }
+
+    #[test]
+    fn split_pt1_hiding() {
+        // Shares of a 1-byte message still differ from each other and carry the indices 1 to n
+
+        let shares = split("a", 2, 3).unwrap();
+        let bytes: Vec<Vec<u8>> = shares.iter().map(|share| read_share(share).unwrap().bytes).collect();
+
+        assert_ne!(bytes[0], bytes[1]);
+        assert_ne!(bytes[1], bytes[2]);
+        assert_eq!(vec![1, 2, 3], shares.iter().map(|share| read_share(share).unwrap().index).collect::<Vec<u8>>());
+    }
+}
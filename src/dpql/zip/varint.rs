@@ -0,0 +1,84 @@
+// LEB128 variable-length integers: seven bits per byte, least significant group first, high bit set on every
+// byte except the last
+
+use crate::error::{Error, Result};
+
+// write_u64 function appends value to out as a LEB128 varint of 1 to 10 bytes
+
+pub fn write_u64(value: u64, out: &mut Vec<u8>) {
+    let mut value = value;
+
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+// read_u64 function reads a LEB128 varint starting at bytes[*pos] and moves pos past it
+
+pub fn read_u64(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value: u64 = 0;
+    let mut shift: u32 = 0;
+
+    loop {
+        let byte = match bytes.get(*pos) {
+            Some(&byte) => byte,
+            None => return Err(Error::TruncatedHeader { expected: *pos + 1, found: bytes.len() }),
+        };
+        *pos += 1;
+
+        // The tenth byte may only carry the single remaining bit of a u64
+        if shift == 63 && byte > 1 {
+            return Err(Error::InvalidVarint);
+        }
+
+        value |= u64::from(byte & 0x7F) << shift;
+
+        if byte & 0x80 == 0 {
+            // A zero last byte after the first would give the value a second, longer encoding
+            if byte == 0 && shift > 0 {
+                return Err(Error::InvalidVarint);
+            }
+
+            return Ok(value);
+        }
+
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod varint_test {
+    use super::*;
+
+    #[test]
+    fn write_read_pt1_values() {
+        // Small values take one byte, u64::MAX takes ten
+
+        for (value, len) in [(0u64, 1), (127, 1), (128, 2), (300, 2), (1 << 35, 6), (u64::MAX, 10)] {
+            let mut out: Vec<u8> = Vec::new();
+            write_u64(value, &mut out);
+
+            let mut pos = 0;
+            assert_eq!(len, out.len());
+            assert_eq!(Ok(value), read_u64(&out, &mut pos));
+            assert_eq!(len, pos);
+        }
+    }
+
+    #[test]
+    fn read_pt1_invalid() {
+        // A missing last byte, a value above u64::MAX and a zero-padded encoding are rejected
+
+        assert_eq!(Err(Error::TruncatedHeader { expected: 2, found: 1 }), read_u64(&[0x80], &mut 0));
+
+        let too_big = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02];
+        assert_eq!(Err(Error::InvalidVarint), read_u64(&too_big, &mut 0));
+
+        // Every value has only its shortest encoding
+        assert_eq!(Err(Error::InvalidVarint), read_u64(&[0x80, 0x00], &mut 0));
+        assert_eq!(Err(Error::InvalidVarint), read_u64(&[0xAC, 0x82, 0x00], &mut 0));
+    }
+}
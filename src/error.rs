@@ -0,0 +1,192 @@
+// Error type shared by every stage of the diropqlz pipeline
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // The DIROPQLZ magic string was not found in the input
+    BadMagic,
+
+    // The decoded input is shorter than the header it must contain
+    TruncatedHeader { expected: usize, found: usize },
+
+    // The text after the magic string is not valid Base85
+    InvalidBase85,
+
+    // The Huffman bit stream or code lengths do not match a valid codebook
+    CodebookViolation,
+
+    // The stored BWT index does not point inside the BWT string or at its sentinel
+    BwtIndexOutOfRange { index: u64, len: usize },
+
+    // A p or q command has no matching partner at instruction index pos
+    UnbalancedLoop { pos: usize },
+
+    // Strict mode refused a program with these unmatched p and q commands
+    InvalidProgram(Vec<crate::dpql::validate::Diagnostic>),
+
+    // The machine model has a tape of zero cells
+    EmptyTape,
+
+    // An l or r command moved the memory pointer off a tape whose pointer overflow policy is Error
+    PointerOutOfRange,
+
+    // An i or d command, or a multiply loop, left the cell range under the cell overflow policy Error
+    CellOverflow,
+
+    // A character or MTF rank is not part of the MTF alphabet
+    SymbolNotInAlphabet,
+
+    // A zero run in the RLE stream is too long to be represented
+    InvalidRunLength,
+
+    // A message, or one block of it, has a diropql program longer than zip::MAX_PROGRAM_LEN
+    ProgramTooLong { len: u64 },
+
+    // The payload expands to a BWT string of another length than the one stored in the header
+    ProgramLengthMismatch { expected: u64, found: usize },
+
+    // The decoded bytes were requested as text but are not valid UTF-8
+    InvalidUtf8,
+
+    // The format version after the magic string is newer than this build or otherwise unknown
+    UnsupportedVersion(u8),
+
+    // The feature flags after the format version include these flags, which this build does not know
+    UnsupportedFlags(u8),
+
+    // A block mode file was given to a single-block reader, or the other way around
+    BlockModeMismatch { blocks: bool },
+
+    // The payload of block index is shorter than the length stored in its header
+    TruncatedBlock { index: usize },
+
+    // Bytes of this length follow the last block of a block mode file
+    TrailingBlockData { len: usize },
+
+    // The stored checksum of the payload, or of the decoded message, does not match its contents
+    ChecksumMismatch { payload: bool },
+
+    // The Reed–Solomon parity count is not between 1 and 254
+    InvalidRedundancy(u8),
+
+    // Reed–Solomon codeword index has more wrong bytes than its check bytes can correct
+    UncorrectableCodeword { index: usize },
+
+    // An encrypted file was read without a passphrase, or a file without encryption was read with one
+    EncryptionMismatch { encrypted: bool },
+
+    // The passphrase is wrong or the encrypted payload or its header was changed
+    DecryptionFailed,
+
+    // The Argon2id costs of an encrypted file are above the limits this build derives keys with
+    ExcessiveCost { memory_kib: u32, iterations: u32 },
+
+    // None of the recipient stanzas opens with the given secret key
+    NotARecipient,
+
+    // The file was expected to be signed but carries no signature
+    MissingSignature,
+
+    // The signature does not match the header and payload, or was made by another key than the expected sender
+    InvalidSignature,
+
+    // A key file or key string is not a valid diropqlz key
+    InvalidKey,
+
+    // A message cannot be split into shares shares with this threshold; it must be between 1 and shares
+    InvalidThreshold { threshold: u8, shares: u8 },
+
+    // A share was given to a reader of whole files, or a whole file was given where a share is expected
+    ShareMismatch { share: bool },
+
+    // A share has index 0 or threshold 0
+    InvalidShare,
+
+    // The shares come from different splits
+    InconsistentShares,
+
+    // The share with this index was given more than once
+    DuplicateShare(u8),
+
+    // Fewer shares were given than the threshold of their split
+    NotEnoughShares { threshold: u8, found: usize },
+
+    // A LEB128 varint in the header does not fit in a u64 or is not in its shortest encoding
+    InvalidVarint,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadMagic => write!(f, "missing DIROPQLZ magic string"),
+            Error::TruncatedHeader { expected, found } => {
+                write!(f, "truncated header: expected at least {} bytes, found {}", expected, found)
+            }
+            Error::InvalidBase85 => write!(f, "invalid base85 data"),
+            Error::CodebookViolation => write!(f, "huffman data does not match its codebook"),
+            Error::BwtIndexOutOfRange { index, len } => {
+                write!(f, "bwt index {} is out of range for length {}", index, len)
+            }
+            Error::UnbalancedLoop { pos } => write!(f, "unbalanced loop command at index {}", pos),
+            Error::InvalidProgram(diagnostics) => {
+                write!(f, "invalid diropql program")?;
+
+                for diagnostic in diagnostics {
+                    write!(f, "; {}", diagnostic)?;
+                }
+
+                Ok(())
+            }
+            Error::EmptyTape => write!(f, "the diropql tape has no cells"),
+            Error::PointerOutOfRange => write!(f, "memory pointer moved off the end of the tape"),
+            Error::CellOverflow => write!(f, "memory cell value out of range"),
+            Error::SymbolNotInAlphabet => write!(f, "symbol not found in mtf alphabet"),
+            Error::InvalidRunLength => write!(f, "invalid rle zero run length"),
+            Error::ProgramTooLong { len } => write!(f, "diropql program of {} symbols is above the supported limit", len),
+            Error::ProgramLengthMismatch { expected, found } => {
+                write!(f, "payload expands to {} symbols instead of the {} stored in the header", found, expected)
+            }
+            Error::InvalidUtf8 => write!(f, "decoded message is not valid UTF-8"),
+            Error::UnsupportedVersion(version) => write!(f, "unsupported diropqlz format version {}", version),
+            Error::UnsupportedFlags(flags) => write!(f, "unsupported diropqlz feature flags {:#04x}", flags),
+            Error::BlockModeMismatch { blocks: true } => write!(f, "file is in block mode"),
+            Error::BlockModeMismatch { blocks: false } => write!(f, "file is not in block mode"),
+            Error::TruncatedBlock { index } => write!(f, "block {} is truncated", index),
+            Error::TrailingBlockData { len } => write!(f, "{} unexpected bytes follow the last block", len),
+            Error::ChecksumMismatch { payload: true } => write!(f, "checksum mismatch: the payload is corrupted"),
+            Error::ChecksumMismatch { payload: false } => write!(f, "checksum mismatch: the decoded message is corrupted"),
+            Error::InvalidRedundancy(parity) => write!(f, "invalid reed-solomon parity count {}", parity),
+            Error::UncorrectableCodeword { index } => {
+                write!(f, "reed-solomon codeword {} has too many errors to correct", index)
+            }
+            Error::EncryptionMismatch { encrypted: true } => write!(f, "file is encrypted; a passphrase is required"),
+            Error::EncryptionMismatch { encrypted: false } => write!(f, "file is not encrypted"),
+            Error::DecryptionFailed => write!(f, "decryption failed: wrong passphrase or tampered data"),
+            Error::ExcessiveCost { memory_kib, iterations } => {
+                write!(f, "argon2 cost of {} KiB and {} passes is above the supported limit", memory_kib, iterations)
+            }
+            Error::NotARecipient => write!(f, "the secret key is not one of the recipients"),
+            Error::MissingSignature => write!(f, "file is not signed"),
+            Error::InvalidSignature => write!(f, "signature verification failed"),
+            Error::InvalidKey => write!(f, "invalid diropqlz key"),
+            Error::InvalidThreshold { threshold, shares } => {
+                write!(f, "cannot split into {} shares with threshold {}", shares, threshold)
+            }
+            Error::ShareMismatch { share: true } => write!(f, "file is a secret share; combine it with the other shares"),
+            Error::ShareMismatch { share: false } => write!(f, "file is not a secret share"),
+            Error::InvalidShare => write!(f, "invalid secret share"),
+            Error::InconsistentShares => write!(f, "the shares belong to different splits"),
+            Error::DuplicateShare(index) => write!(f, "share {} was given more than once", index),
+            Error::NotEnoughShares { threshold, found } => {
+                write!(f, "not enough shares: {} needed, {} given", threshold, found)
+            }
+            Error::InvalidVarint => write!(f, "header varint does not fit in 64 bits or is not in its shortest encoding"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Result type returned by every fallible function of the crate
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,118 @@
+// Message Obfuscator library: converts messages to diropql programs and compresses them into diropqlz strings
+
+// Explicit return statements are the house style of this crate
+#![allow(clippy::needless_return)]
+
+// Declare modules
+pub mod dpql;
+pub mod compressor;
+pub mod error;
+
+// Error type returned by every read and decode function
+pub use error::{Error, Result};
+
+// Individual transform stages of the diropqlz pipeline
+pub use compressor::{bwt, huffman, mtf, rle};
+
+// Metadata struct stored in the header of a diropqlz string
+pub use dpql::zip::DpqlzMeta;
+
+// Key derivation and cipher parameters of an encrypted diropqlz string
+pub use dpql::zip::crypt::Encryption;
+
+// Keypairs used to encrypt to recipients and to sign as a sender
+pub use dpql::zip::keys::{PublicKey, SecretKey};
+
+// Limits and outcome of running an untrusted diropql program
+pub use dpql::bytecode::{Limits, Outcome, StopReason};
+
+// Machine model a diropql program runs on
+pub use dpql::machine::{CellOverflow, CellWidth, MachineConfig, PointerOverflow};
+
+// Options of the multithreaded block mode
+pub use dpql::zip::block::BlockOptions;
+
+// obfuscate function converts a message to a diropqlz string
+
+pub fn obfuscate(text: &str) -> String {
+    return dpql::zip::write(text);
+}
+
+// deobfuscate function converts a diropqlz string back to the original message
+
+pub fn deobfuscate(dpqlz: &str) -> Result<String> {
+    return dpql::zip::read(dpqlz);
+}
+
+// obfuscate_bytes function converts arbitrary binary data to a diropqlz string
+
+pub fn obfuscate_bytes(data: &[u8]) -> String {
+    return dpql::zip::write_bytes(data);
+}
+
+// obfuscate_bytes_with_parity function converts binary data to a diropqlz string that survives up to parity / 2
+// mangled bytes per 255-byte Reed–Solomon codeword; parity must be between 0 and 254
+
+pub fn obfuscate_bytes_with_parity(data: &[u8], parity: u8) -> Result<String> {
+    return dpql::zip::write_bytes_with_parity(data, parity);
+}
+
+// deobfuscate_bytes function converts a diropqlz string back to the original binary data
+
+pub fn deobfuscate_bytes(dpqlz: &str) -> Result<Vec<u8>> {
+    return dpql::zip::read_bytes(dpqlz);
+}
+
+// obfuscate_encrypted function converts binary data to a diropqlz string whose payload is encrypted with the
+// passphrase; parity adds Reed–Solomon check bytes as in obfuscate_bytes_with_parity, 0 adds none
+
+pub fn obfuscate_encrypted(data: &[u8], passphrase: &str, parity: u8) -> Result<String> {
+    return dpql::zip::write_bytes_encrypted_with_parity(data, passphrase, parity);
+}
+
+// deobfuscate_encrypted function converts an encrypted diropqlz string back to the original binary data; a wrong
+// passphrase or tampered data returns Error::DecryptionFailed
+
+pub fn deobfuscate_encrypted(dpqlz: &str, passphrase: &str) -> Result<Vec<u8>> {
+    return dpql::zip::read_bytes_encrypted(dpqlz, passphrase);
+}
+
+// obfuscate_to_recipients function converts binary data to a diropqlz string that only the recipients can open,
+// signed by the sender if one is given; parity adds Reed–Solomon check bytes, 0 adds none
+
+pub fn obfuscate_to_recipients(data: &[u8], recipients: &[PublicKey], sender: Option<&SecretKey>, parity: u8) -> Result<String> {
+    return dpql::zip::keys::write_bytes(data, recipients, sender, parity);
+}
+
+// deobfuscate_with_key function converts a diropqlz string encrypted to the secret key back to the original data;
+// it does not authenticate the sender, as a signature can be stripped, so call verify_sender first for that
+
+pub fn deobfuscate_with_key(dpqlz: &str, secret: &SecretKey) -> Result<Vec<u8>> {
+    return dpql::zip::keys::read_bytes(dpqlz, secret);
+}
+
+// verify_sender function checks that a diropqlz string was signed by the sender and not changed since; an
+// unsigned string fails
+
+pub fn verify_sender(dpqlz: &str, sender: &PublicKey) -> Result<()> {
+    return dpql::zip::keys::verify(dpqlz, sender);
+}
+
+// obfuscate_shares function splits binary data into `shares` diropqlz strings so that any `threshold` of them
+// recover it and fewer reveal nothing about it
+
+pub fn obfuscate_shares(data: &[u8], threshold: u8, shares: u8) -> Result<Vec<String>> {
+    return dpql::zip::shamir::split_bytes(data, threshold, shares);
+}
+
+// deobfuscate_shares function recovers the original binary data from at least threshold shares of one split
+
+pub fn deobfuscate_shares(shares: &[&str]) -> Result<Vec<u8>> {
+    return dpql::zip::shamir::combine_bytes(shares);
+}
+
+// obfuscate_blocks function converts binary data to a block mode diropqlz string, compressing the blocks in parallel
+
+pub fn obfuscate_blocks(data: &[u8], options: &BlockOptions) -> String {
+    return dpql::zip::block::write_bytes(data, options);
+}
@@ -1,6 +1,860 @@
-mod dpql;
-mod compressor;
+// Explicit return statements are the house style of this crate
+#![allow(clippy::needless_return)]
+
+use midterm_problem::{deobfuscate_bytes, deobfuscate_encrypted, deobfuscate_shares, deobfuscate_with_key, dpql, obfuscate_blocks};
+use midterm_problem::{obfuscate_bytes, obfuscate_bytes_with_parity, obfuscate_encrypted, obfuscate_shares, obfuscate_to_recipients};
+use midterm_problem::verify_sender;
+use midterm_problem::{BlockOptions, CellOverflow, CellWidth, Limits, MachineConfig, PointerOverflow, PublicKey, SecretKey, StopReason};
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+// Exit codes returned by the command-line tool
+const EXIT_OK: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "\
+Usage: Midterm_Problem <COMMAND> [-i <FILE>] [-o <FILE>]
+
+Commands:
+    obfuscate      Convert a message into a diropqlz string
+    deobfuscate    Convert a diropqlz string back into the message
+    dpql-emit      Convert a message into a diropql program
+    dpql-run       Run a diropql program and print its output
+    dpql-check     Report every unmatched p and q command of a diropql program
+    keygen         Generate a secret key; with '-o FILE' its public key is also written to FILE.pub
+    encrypt        Obfuscate a message that only the recipients can read
+    decrypt        Read a message encrypted to your key; only '--sender' makes it check who sent it
+    verify         Check that a diropqlz string was signed by the sender
+    split          Split a message into shares, one per line, any threshold of which recover it
+    combine        Recover a message from shares given one per line
+
+Options:
+    -i, --input <FILE>     Read input from FILE instead of stdin ('-' for stdin)
+    -o, --output <FILE>    Write output to FILE instead of stdout ('-' for stdout)
+    -b, --block-size <N>   Obfuscate in block mode with N message bytes per block
+    -j, --threads <N>      Number of threads used by the block mode
+    -r, --redundancy <N>   Add N Reed-Solomon check bytes (1-254) per 255-byte codeword
+    -p, --passphrase-file <FILE>
+                           Encrypt or decrypt with the passphrase on the first line of FILE
+    -k, --key <FILE>       Your secret key: signs with encrypt, opens messages with decrypt
+    -R, --recipient <KEY>  Public key, or file holding one, to encrypt to; may be repeated
+    -s, --sender <KEY>     Public key, or file holding one, that must have signed the message
+    -m, --threshold <K>    Number of shares needed to recover a split message
+    -n, --shares <N>       Number of shares to split a message into (1-255)
+    --max-steps <N>        Stop dpql-run after N instructions
+    --max-output <N>       Stop dpql-run before it outputs more than N bytes
+    --strict               Refuse to run a diropql program with unmatched p or q commands
+    -f, --force            Let keygen replace an existing secret key file
+    --tape <N|unbounded>   Run dpql-run on N cells, or on a tape that grows in both directions
+    --cell-width <BITS>    Width of every dpql-run cell: 8, 16 or 32
+    --pointer-overflow <POLICY>
+                           What dpql-run does at an end of the tape: wrap, error or grow
+    --cell-overflow <POLICY>
+                           What dpql-run does at an end of a cell's range: wrap, saturate or error
+    -h, --help             Print this help message";
+
+// Subcommands supported by the command-line tool
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Obfuscate,
+    Deobfuscate,
+    DpqlEmit,
+    DpqlRun,
+    DpqlCheck,
+    Keygen,
+    Encrypt,
+    Decrypt,
+    Verify,
+    Split,
+    Combine,
+}
+
+// Parsed command line arguments; None for input or output means stdin or stdout
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: Command,
+    input: Option<String>,
+    output: Option<String>,
+    block_size: Option<usize>,
+    threads: Option<usize>,
+    redundancy: Option<u8>,
+    passphrase_file: Option<String>,
+    key_file: Option<String>,
+    recipients: Vec<String>,
+    sender: Option<String>,
+    threshold: Option<u8>,
+    shares: Option<u8>,
+    max_steps: Option<u64>,
+    max_output: Option<usize>,
+    strict: bool,
+    machine: MachineConfig,
+    force: bool,
+}
+
+// parse_args function converts the command line arguments (without the program name) to an Args struct
+
+fn parse_args(args: &[String]) -> Result<Option<Args>, String> {
+    let mut command: Option<Command> = None;
+    let mut input: Option<String> = None;
+    let mut output: Option<String> = None;
+    let mut block_size: Option<usize> = None;
+    let mut threads: Option<usize> = None;
+    let mut redundancy: Option<u8> = None;
+    let mut passphrase_file: Option<String> = None;
+    let mut key_file: Option<String> = None;
+    let mut recipients: Vec<String> = Vec::new();
+    let mut sender: Option<String> = None;
+    let mut threshold: Option<u8> = None;
+    let mut shares: Option<u8> = None;
+    let mut max_steps: Option<u64> = None;
+    let mut max_output: Option<usize> = None;
+    let mut strict = false;
+    let mut machine = MachineConfig::default();
+    let mut force = false;
+
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+
+            "-i" | "--input" | "-o" | "--output" => {
+                let value = match iter.next() {
+                    Some(value) => value.clone(),
+                    None => return Err(format!("missing value for '{}'", arg)),
+                };
+
+                // A single dash keeps the standard stream
+                let value = if value == "-" { None } else { Some(value) };
+
+                if arg == "-i" || arg == "--input" {
+                    input = value;
+                }
+
+                else {
+                    output = value;
+                }
+            }
+
+            "-b" | "--block-size" | "-j" | "--threads" => {
+                let value = match iter.next().map(|value| value.parse::<usize>()) {
+                    Some(Ok(value)) if value > 0 => value,
+                    Some(_) => return Err(format!("'{}' expects a positive number", arg)),
+                    None => return Err(format!("missing value for '{}'", arg)),
+                };
+
+                if arg == "-b" || arg == "--block-size" {
+                    block_size = Some(value);
+                }
+
+                else {
+                    threads = Some(value);
+                }
+            }
+
+            "-p" | "--passphrase-file" | "-k" | "--key" | "-R" | "--recipient" | "-s" | "--sender" => {
+                let value = match iter.next() {
+                    Some(value) => value.clone(),
+                    None => return Err(format!("missing value for '{}'", arg)),
+                };
+
+                match arg.as_str() {
+                    "-p" | "--passphrase-file" => passphrase_file = Some(value),
+                    "-k" | "--key" => key_file = Some(value),
+                    "-R" | "--recipient" => recipients.push(value),
+                    _ => sender = Some(value),
+                }
+            }
+
+            "-r" | "--redundancy" => {
+                redundancy = match iter.next().map(|value| value.parse::<u8>()) {
+                    Some(Ok(value)) if value > 0 && value < 255 => Some(value),
+                    Some(_) => return Err(format!("'{}' expects a number from 1 to 254", arg)),
+                    None => return Err(format!("missing value for '{}'", arg)),
+                };
+            }
+
+            "-m" | "--threshold" | "-n" | "--shares" => {
+                let value = match iter.next().map(|value| value.parse::<u8>()) {
+                    Some(Ok(value)) if value > 0 => value,
+                    Some(_) => return Err(format!("'{}' expects a number from 1 to 255", arg)),
+                    None => return Err(format!("missing value for '{}'", arg)),
+                };
+
+                if arg == "-m" || arg == "--threshold" {
+                    threshold = Some(value);
+                }
+
+                else {
+                    shares = Some(value);
+                }
+            }
+
+            "--strict" => strict = true,
+            "-f" | "--force" => force = true,
+
+            "--max-steps" | "--max-output" => {
+                let value = match iter.next().map(|value| value.parse::<u64>()) {
+                    Some(Ok(value)) => value,
+                    Some(_) => return Err(format!("'{}' expects a number", arg)),
+                    None => return Err(format!("missing value for '{}'", arg)),
+                };
+
+                if arg == "--max-steps" {
+                    max_steps = Some(value);
+                }
+
+                else {
+                    max_output = Some(usize::try_from(value).unwrap_or(usize::MAX));
+                }
+            }
+
+            "--tape" | "--cell-width" | "--pointer-overflow" | "--cell-overflow" => {
+                let value = match iter.next() {
+                    Some(value) => value.as_str(),
+                    None => return Err(format!("missing value for '{}'", arg)),
+                };
+
+                match (arg.as_str(), value) {
+                    ("--tape", "unbounded") => machine.tape_len = None,
+                    ("--tape", _) => match value.parse::<usize>() {
+                        Ok(len) if len > 0 => machine.tape_len = Some(len),
+                        _ => return Err(format!("'{}' expects a positive number or 'unbounded'", arg)),
+                    },
+
+                    ("--cell-width", "8") => machine.cell_width = CellWidth::U8,
+                    ("--cell-width", "16") => machine.cell_width = CellWidth::U16,
+                    ("--cell-width", "32") => machine.cell_width = CellWidth::U32,
+                    ("--cell-width", _) => return Err(format!("'{}' expects 8, 16 or 32", arg)),
+
+                    ("--pointer-overflow", "wrap") => machine.pointer_overflow = PointerOverflow::Wrap,
+                    ("--pointer-overflow", "error") => machine.pointer_overflow = PointerOverflow::Error,
+                    ("--pointer-overflow", "grow") => machine.pointer_overflow = PointerOverflow::Grow,
+                    ("--pointer-overflow", _) => return Err(format!("'{}' expects wrap, error or grow", arg)),
+
+                    (_, "wrap") => machine.cell_overflow = CellOverflow::Wrap,
+                    (_, "saturate") => machine.cell_overflow = CellOverflow::Saturate,
+                    (_, "error") => machine.cell_overflow = CellOverflow::Error,
+                    _ => return Err(format!("'{}' expects wrap, saturate or error", arg)),
+                }
+            }
+
+            _ if command.is_none() => {
+                command = Some(match arg.as_str() {
+                    "obfuscate" => Command::Obfuscate,
+                    "deobfuscate" => Command::Deobfuscate,
+                    "dpql-emit" => Command::DpqlEmit,
+                    "dpql-run" => Command::DpqlRun,
+                    "dpql-check" => Command::DpqlCheck,
+                    "keygen" => Command::Keygen,
+                    "encrypt" => Command::Encrypt,
+                    "decrypt" => Command::Decrypt,
+                    "verify" => Command::Verify,
+                    "split" => Command::Split,
+                    "combine" => Command::Combine,
+                    _ => return Err(format!("unknown command '{}'", arg)),
+                });
+            }
+
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    // Block mode files carry no Reed-Solomon code and no encryption
+    if block_size.is_some() && redundancy.is_some() {
+        return Err(String::from("'--redundancy' cannot be combined with '--block-size'"));
+    }
+
+    if block_size.is_some() && passphrase_file.is_some() {
+        return Err(String::from("'--passphrase-file' cannot be combined with '--block-size'"));
+    }
+
+    // The public-key commands need their keys
+    match command {
+        Some(Command::Encrypt) if recipients.is_empty() => return Err(String::from("'encrypt' needs at least one '--recipient'")),
+        Some(Command::Decrypt) if key_file.is_none() => return Err(String::from("'decrypt' needs '--key'")),
+        Some(Command::Verify) if sender.is_none() => return Err(String::from("'verify' needs '--sender'")),
+        Some(Command::Split) if threshold.is_none() || shares.is_none() => {
+            return Err(String::from("'split' needs '--threshold' and '--shares'"))
+        }
+        _ => {}
+    }
+
+    match command {
+        Some(command) => Ok(Some(Args {
+            command,
+            input,
+            output,
+            block_size,
+            threads,
+            redundancy,
+            passphrase_file,
+            key_file,
+            recipients,
+            sender,
+            threshold,
+            shares,
+            max_steps,
+            max_output,
+            strict,
+            machine,
+            force,
+        })),
+        None => Err(String::from("no command given")),
+    }
+}
+
+// read_input function reads the whole input file, or stdin if no file is given
+
+fn read_input(path: &Option<String>) -> io::Result<Vec<u8>> {
+    match path {
+        Some(path) => fs::read(path),
+
+        None => {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+// write_output function writes the result to the output file, or stdout if no file is given
+
+fn write_output(path: &Option<String>, data: &[u8]) -> io::Result<()> {
+    match path {
+        Some(path) => fs::write(path, data),
+
+        None => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(data)?;
+            stdout.flush()
+        }
+    }
+}
+
+/* write_secret_key function writes a secret key file that only its owner can read; an existing file is only
+replaced with --force, and then also loses any wider permissions. Without a file the key goes to stdout */
+
+fn write_secret_key(path: &Option<String>, data: &[u8], force: bool) -> io::Result<()> {
+    let path = match path {
+        Some(path) => path,
+        None => return write_output(&None, data),
+    };
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+
+    if force {
+        options.create(true).truncate(true);
+    }
+
+    else {
+        options.create_new(true);
+    }
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = match options.open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(io::Error::new(err.kind(), format!("{} already exists; use '--force' to replace it", path)));
+        }
+        Err(err) => return Err(err),
+    };
+
+    // The mode only applies to new files
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+    file.write_all(data)?;
+    file.flush()
+}
+
+// write_public_key function writes the public key next to the secret key file written to path, as path.pub; a
+// new secret key makes any old public key file stale, so it is always replaced
+
+fn write_public_key(path: &Option<String>, secret: &SecretKey) -> io::Result<()> {
+    match path {
+        Some(path) => fs::write(format!("{}.pub", path), format!("{}\n", secret.public_key().to_text())),
+        None => Ok(()),
+    }
+}
+
+// read_passphrase function returns the first line of the passphrase file
+
+fn read_passphrase(path: &str) -> io::Result<String> {
+    let text = fs::read_to_string(path)?;
+
+    Ok(String::from(text.lines().next().unwrap_or("")))
+}
+
+// load_secret_key function reads the secret key file written by keygen
+
+fn load_secret_key(path: &str) -> Result<SecretKey, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+
+    SecretKey::from_text(&text).map_err(|err| format!("{}: {}", path, err))
+}
+
+// load_public_key function reads a public key given as text or as the path of a file holding one
+
+fn load_public_key(key: &str) -> Result<PublicKey, String> {
+    if key.starts_with("dpqlz-public:") {
+        return PublicKey::from_text(key).map_err(|err| err.to_string());
+    }
+
+    let text = fs::read_to_string(key).map_err(|err| format!("{}: {}", key, err))?;
+
+    PublicKey::from_text(&text).map_err(|err| format!("{}: {}", key, err))
+}
+
+// check_program function prints every unmatched p and q command of a diropql program and returns whether it
+// has none
+
+fn check_program(prog: &str) -> bool {
+    let diagnostics = dpql::validate::validate(prog);
+
+    for diagnostic in &diagnostics {
+        eprintln!("error: {}", diagnostic);
+    }
+
+    return diagnostics.is_empty();
+}
+
+// run function executes the parsed command and returns the exit code
+
+fn run(args: &Args) -> i32 {
+    // keygen reads no input
+    let input = match args.command {
+        Command::Keygen => Ok(Vec::new()),
+        _ => read_input(&args.input),
+    };
+
+    let input = match input {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("error: cannot read input: {}", err);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let secret = args.key_file.as_deref().map(load_secret_key).transpose();
+    let recipients: Result<Vec<PublicKey>, String> = args.recipients.iter().map(|key| load_public_key(key)).collect();
+    let sender = args.sender.as_deref().map(load_public_key).transpose();
+
+    let (secret, recipients, sender) = match (secret, recipients, sender) {
+        (Ok(secret), Ok(recipients), Ok(sender)) => (secret, recipients, sender),
+        (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
+            eprintln!("error: cannot read key: {}", err);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let passphrase = match args.passphrase_file.as_deref().map(read_passphrase) {
+        Some(Ok(passphrase)) => Some(passphrase),
+        Some(Err(err)) => {
+            eprintln!("error: cannot read passphrase: {}", err);
+            return EXIT_FAILURE;
+        }
+        None => None,
+    };
+
+    // keygen writes both halves of the keypair, so it is made before the output
+    let keypair = match args.command {
+        Command::Keygen => Some(SecretKey::generate()),
+        _ => None,
+    };
+
+    // Messages are arbitrary bytes; diropqlz strings and diropql programs are text,
+    // often saved with a trailing newline
+    let output = match args.command {
+        Command::Obfuscate => match args.block_size {
+            Some(block_size) => {
+                let mut options = BlockOptions { block_size, ..BlockOptions::default() };
+                options.threads = args.threads.unwrap_or(options.threads);
+
+                Ok(obfuscate_blocks(&input, &options).into_bytes())
+            }
+
+            None => match (&passphrase, args.redundancy) {
+                (Some(passphrase), parity) => obfuscate_encrypted(&input, passphrase, parity.unwrap_or(0)).map(String::into_bytes),
+                (None, Some(parity)) => obfuscate_bytes_with_parity(&input, parity).map(String::into_bytes),
+                (None, None) => Ok(obfuscate_bytes(&input).into_bytes()),
+            },
+        },
+        Command::Deobfuscate => match &passphrase {
+            Some(passphrase) => deobfuscate_encrypted(String::from_utf8_lossy(&input).trim_end(), passphrase),
+            None => deobfuscate_bytes(String::from_utf8_lossy(&input).trim_end()),
+        },
+        Command::DpqlEmit => Ok(dpql::write_bytes(&input).into_bytes()),
+        Command::DpqlRun => {
+            let prog = String::from_utf8_lossy(&input);
+
+            if args.strict && !check_program(&prog) {
+                return EXIT_FAILURE;
+            }
+
+            let limits = Limits { max_steps: args.max_steps, max_output: args.max_output, cancel: None };
+            let outcome = dpql::execute_with_config(&prog, &args.machine, &limits);
+
+            match outcome.stop {
+                StopReason::Finished => Ok(outcome.output),
+                reason => {
+                    // Keep what the program printed before it was stopped
+                    if let Err(err) = write_output(&args.output, &outcome.output) {
+                        eprintln!("error: cannot write output: {}", err);
+                    }
+
+                    eprintln!("error: program stopped after {} steps: {}", outcome.steps, reason);
+                    return EXIT_FAILURE;
+                }
+            }
+        }
+        Command::DpqlCheck => {
+            if !check_program(&String::from_utf8_lossy(&input)) {
+                return EXIT_FAILURE;
+            }
+
+            Ok(Vec::new())
+        }
+        Command::Keygen => {
+            let secret = keypair.as_ref().expect("the keypair is made for keygen");
+
+            Ok(format!("# public key: {}\n{}\n", secret.public_key().to_text(), secret.to_text()).into_bytes())
+        }
+        Command::Encrypt => {
+            obfuscate_to_recipients(&input, &recipients, secret.as_ref(), args.redundancy.unwrap_or(0)).map(String::into_bytes)
+        }
+        Command::Decrypt => {
+            let dpqlz = String::from_utf8_lossy(&input);
+            let secret = secret.as_ref().expect("parse_args requires a key for decrypt");
+
+            // With a sender, only messages it signed are opened
+            match &sender {
+                Some(sender) => verify_sender(dpqlz.trim_end(), sender).and_then(|()| deobfuscate_with_key(dpqlz.trim_end(), secret)),
+                None => deobfuscate_with_key(dpqlz.trim_end(), secret),
+            }
+        }
+        Command::Verify => {
+            let sender = sender.as_ref().expect("parse_args requires a sender for verify");
+
+            verify_sender(String::from_utf8_lossy(&input).trim_end(), sender)
+                .map(|()| format!("Good signature from {}\n", sender.to_text()).into_bytes())
+        }
+        Command::Split => {
+            let threshold = args.threshold.expect("parse_args requires a threshold for split");
+            let shares = args.shares.expect("parse_args requires a share count for split");
+
+            obfuscate_shares(&input, threshold, shares).map(|shares| (shares.join("\n") + "\n").into_bytes())
+        }
+        Command::Combine => {
+            let input = String::from_utf8_lossy(&input);
+            let shares: Vec<&str> = input.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+
+            deobfuscate_shares(&shares)
+        }
+    };
+
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let written = match args.command {
+        Command::Keygen => write_secret_key(&args.output, &output, args.force)
+            .and_then(|()| write_public_key(&args.output, keypair.as_ref().expect("the keypair is made for keygen"))),
+        _ => write_output(&args.output, &output),
+    };
+
+    if let Err(err) = written {
+        eprintln!("error: cannot write output: {}", err);
+        return EXIT_FAILURE;
+    }
+
+    EXIT_OK
+}
 
 fn main() {
-    
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let code = match parse_args(&args) {
+        Ok(Some(args)) => run(&args),
+
+        Ok(None) => {
+            println!("{}", USAGE);
+            EXIT_OK
+        }
+
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            EXIT_USAGE
+        }
+    };
+
+    process::exit(code);
+}
+
+#[cfg(test)]
+mod main_test {
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| String::from(*s)).collect()
+    }
+
+    // base_args function returns what parse_args gives for the bare command: the standard streams and no options
+
+    fn base_args(command: Command) -> Args {
+        return Args {
+            command,
+            input: None,
+            output: None,
+            block_size: None,
+            threads: None,
+            redundancy: None,
+            passphrase_file: None,
+            key_file: None,
+            recipients: Vec::new(),
+            sender: None,
+            threshold: None,
+            shares: None,
+            max_steps: None,
+            max_output: None,
+            strict: false,
+            machine: MachineConfig::default(),
+            force: false,
+        };
+    }
+
+    #[test]
+    fn parse_args_pt1_command() {
+        // A bare command reads stdin and writes stdout
+
+        let received = parse_args(&to_args(&["obfuscate"]));
+        let expected = base_args(Command::Obfuscate);
+
+        assert_eq!(Ok(Some(expected)), received);
+    }
+
+    #[test]
+    fn parse_args_pt2_files() {
+        // Options may come before or after the command; '-' keeps the standard stream
+
+        let received = parse_args(&to_args(&["-i", "msg.txt", "dpql-run", "--output", "-"]));
+        let expected = Args {
+            input: Some(String::from("msg.txt")),
+            ..base_args(Command::DpqlRun)
+        };
+
+        assert_eq!(Ok(Some(expected)), received);
+    }
+
+    #[test]
+    fn parse_args_pt3_help() {
+        assert_eq!(Ok(None), parse_args(&to_args(&["deobfuscate", "--help"])));
+    }
+
+    #[test]
+    fn parse_args_pt4_errors() {
+        // Missing command, unknown command, missing option value and extra arguments are rejected
+
+        assert!(parse_args(&to_args(&[])).is_err());
+        assert!(parse_args(&to_args(&["compress"])).is_err());
+        assert!(parse_args(&to_args(&["obfuscate", "-i"])).is_err());
+        assert!(parse_args(&to_args(&["obfuscate", "dpql-run"])).is_err());
+        assert!(parse_args(&to_args(&["obfuscate", "-b", "0"])).is_err());
+        assert!(parse_args(&to_args(&["obfuscate", "-j", "many"])).is_err());
+        assert!(parse_args(&to_args(&["obfuscate", "-r", "255"])).is_err());
+        assert!(parse_args(&to_args(&["obfuscate", "-r", "8", "-b", "4096"])).is_err());
+        assert!(parse_args(&to_args(&["obfuscate", "-p", "key.txt", "-b", "4096"])).is_err());
+        assert!(parse_args(&to_args(&["deobfuscate", "--passphrase-file"])).is_err());
+        assert!(parse_args(&to_args(&["encrypt", "-k", "me.key"])).is_err());
+        assert!(parse_args(&to_args(&["decrypt", "-R", "bob.pub"])).is_err());
+        assert!(parse_args(&to_args(&["verify", "-k", "me.key"])).is_err());
+        assert!(parse_args(&to_args(&["split", "-m", "2"])).is_err());
+        assert!(parse_args(&to_args(&["split", "-m", "2", "-n", "0"])).is_err());
+        assert!(parse_args(&to_args(&["dpql-run", "--max-steps", "-5"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_pt5_blocks() {
+        // Block size and thread count select the block mode
+
+        let received = parse_args(&to_args(&["obfuscate", "--block-size", "4096", "-j", "3"]));
+        let expected = Args {
+            block_size: Some(4096),
+            threads: Some(3),
+            ..base_args(Command::Obfuscate)
+        };
+
+        assert_eq!(Ok(Some(expected)), received);
+    }
+
+    #[test]
+    fn parse_args_pt6_redundancy() {
+        // The redundancy is the number of Reed-Solomon check bytes per codeword
+
+        let received = parse_args(&to_args(&["obfuscate", "--redundancy", "16"]));
+        let expected = Args {
+            redundancy: Some(16),
+            ..base_args(Command::Obfuscate)
+        };
+
+        assert_eq!(Ok(Some(expected)), received);
+    }
+
+    #[test]
+    fn parse_args_pt7_passphrase() {
+        // The passphrase is read from a file so that it does not show up in the process list
+
+        let received = parse_args(&to_args(&["deobfuscate", "-p", "key.txt", "-i", "msg.dpqlz"]));
+        let expected = Args {
+            input: Some(String::from("msg.dpqlz")),
+            passphrase_file: Some(String::from("key.txt")),
+            ..base_args(Command::Deobfuscate)
+        };
+
+        assert_eq!(Ok(Some(expected)), received);
+    }
+
+    #[test]
+    fn parse_args_pt8_keys() {
+        // Recipients may be repeated; the key signs and the sender is checked
+
+        let received = parse_args(&to_args(&["encrypt", "-R", "alice.pub", "--recipient", "bob.pub", "-k", "me.key", "-s", "me.pub"]));
+        let expected = Args {
+            key_file: Some(String::from("me.key")),
+            recipients: vec![String::from("alice.pub"), String::from("bob.pub")],
+            sender: Some(String::from("me.pub")),
+            ..base_args(Command::Encrypt)
+        };
+
+        assert_eq!(Ok(Some(expected)), received);
+        assert_eq!(Command::Keygen, parse_args(&to_args(&["keygen"])).unwrap().unwrap().command);
+    }
+
+    #[test]
+    fn parse_args_pt9_shares() {
+        // split takes a threshold and a share count; combine needs neither
+
+        let received = parse_args(&to_args(&["split", "--threshold", "3", "-n", "5", "-o", "shares.txt"]));
+        let expected = Args {
+            output: Some(String::from("shares.txt")),
+            threshold: Some(3),
+            shares: Some(5),
+            ..base_args(Command::Split)
+        };
+
+        assert_eq!(Ok(Some(expected)), received);
+        assert_eq!(Command::Combine, parse_args(&to_args(&["combine"])).unwrap().unwrap().command);
+    }
+
+    #[test]
+    fn parse_args_pt10_limits() {
+        // dpql-run takes a step budget, an output cap and strict mode
+
+        let received = parse_args(&to_args(&["dpql-run", "--max-steps", "1000000", "--max-output", "0"]));
+        let expected = Args {
+            max_steps: Some(1_000_000),
+            max_output: Some(0),
+            ..base_args(Command::DpqlRun)
+        };
+
+        assert_eq!(Ok(Some(expected)), received);
+        assert!(parse_args(&to_args(&["dpql-run", "--strict"])).unwrap().unwrap().strict);
+        assert_eq!(Command::DpqlCheck, parse_args(&to_args(&["dpql-check"])).unwrap().unwrap().command);
+    }
+
+    #[test]
+    fn parse_args_pt11_machine() {
+        // dpql-run takes the machine model; the options left out keep the default machine
+
+        let received = parse_args(&to_args(&["dpql-run", "--tape", "unbounded", "--cell-width", "16", "--cell-overflow", "saturate"]));
+        let expected = MachineConfig {
+            tape_len: None,
+            cell_width: CellWidth::U16,
+            pointer_overflow: PointerOverflow::Wrap,
+            cell_overflow: CellOverflow::Saturate,
+        };
+
+        assert_eq!(expected, received.unwrap().unwrap().machine);
+
+        let received = parse_args(&to_args(&["dpql-run", "--tape", "30000", "--pointer-overflow", "error"])).unwrap().unwrap();
+        assert_eq!((Some(30000), PointerOverflow::Error), (received.machine.tape_len, received.machine.pointer_overflow));
+
+        assert!(parse_args(&to_args(&["dpql-run", "--tape", "0"])).is_err());
+        assert!(parse_args(&to_args(&["dpql-run", "--cell-width", "64"])).is_err());
+        assert!(parse_args(&to_args(&["dpql-run", "--pointer-overflow", "saturate"])).is_err());
+        assert!(parse_args(&to_args(&["dpql-run", "--cell-overflow", "grow"])).is_err());
+    }
+
+    #[test]
+    fn write_secret_key_pt1_permissions() {
+        // A key file is private, is not replaced by accident and is replaced with --force
+
+        let path = env::temp_dir().join(format!("dpqlz-keygen-test-{}.key", process::id()));
+        let path_arg = Some(path.to_string_lossy().into_owned());
+        let _ = fs::remove_file(&path);
+
+        assert!(write_secret_key(&path_arg, b"first", false).is_ok());
+        assert_eq!(io::ErrorKind::AlreadyExists, write_secret_key(&path_arg, b"second", false).unwrap_err().kind());
+        assert_eq!(b"first".to_vec(), fs::read(&path).unwrap());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(write_secret_key(&path_arg, b"second", true).is_ok());
+            assert_eq!(0o600, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+        }
+
+        assert!(write_secret_key(&path_arg, b"second", true).is_ok());
+        assert_eq!(b"second".to_vec(), fs::read(&path).unwrap());
+        assert!(parse_args(&to_args(&["keygen", "-o", "me.key", "--force"])).unwrap().unwrap().force);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn run_pt1_keys() {
+        // Key files written by keygen encrypt, decrypt, sign and verify without being edited by hand
+
+        let dir = env::temp_dir().join(format!("dpqlz-keys-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+
+        let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let run_args = |args: &[&str]| run(&parse_args(&to_args(args)).unwrap().unwrap());
+
+        fs::write(file("msg.txt"), "meet at noon").unwrap();
+
+        assert_eq!(EXIT_OK, run_args(&["keygen", "-o", &file("alice.key")]));
+        assert_eq!(EXIT_OK, run_args(&["keygen", "-o", &file("bob.key")]));
+        assert!(fs::read_to_string(file("alice.key.pub")).unwrap().starts_with("dpqlz-public:"));
+
+        let encrypt = ["encrypt", "-i", &file("msg.txt"), "-o", &file("msg.dpqlz"), "-R", &file("bob.key.pub"), "-k", &file("alice.key")];
+        assert_eq!(EXIT_OK, run_args(&encrypt));
+
+        let decrypt = ["decrypt", "-i", &file("msg.dpqlz"), "-o", &file("out.txt"), "-k", &file("bob.key"), "-s", &file("alice.key.pub")];
+        assert_eq!(EXIT_OK, run_args(&decrypt));
+        assert_eq!("meet at noon", fs::read_to_string(file("out.txt")).unwrap());
+
+        assert_eq!(EXIT_OK, run_args(&["verify", "-i", &file("msg.dpqlz"), "-s", &file("alice.key.pub"), "-o", &file("verify.txt")]));
+        assert_eq!(EXIT_FAILURE, run_args(&["verify", "-i", &file("msg.dpqlz"), "-s", &file("bob.key.pub")]));
+        assert_eq!(EXIT_FAILURE, run_args(&["decrypt", "-i", &file("msg.dpqlz"), "-k", &file("alice.key")]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }